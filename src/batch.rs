@@ -1,4 +1,4 @@
-use std::ops::{Add, Mul};
+use std::ops::{Add, Mul, Sub};
 
 use cosmwasm_std::{Timestamp, Uint128};
 
@@ -7,6 +7,11 @@ use crate::{
     ContractError,
 };
 
+// Batch can be claimed at the given time
+pub fn is_mature(batch: &Batch, time: Timestamp) -> bool {
+    !batch.released && batch.release_time < time
+}
+
 pub fn create_batches(
     batch_duration: Uint128,
     batch_amount: Uint128,
@@ -34,13 +39,91 @@ pub fn update_batches(
     amount: Uint128,
     batch_amount: Uint128,
 ) -> Result<Bathces, ContractError> {
+    // tokens added to a released batch could never be claimed
+    if batches.iter().any(|batch| batch.released) {
+        return Err(ContractError::BatchReleased {});
+    }
     let new_batces = batches
         .iter()
         .map(|batch| Batch {
             amount: batch.amount.add(amount / batch_amount),
             release_time: batch.release_time,
-            released: false,
+            released: batch.released,
         })
         .collect();
     Ok(new_batces)
 }
+
+pub fn reduce_batches(
+    batches: Bathces,
+    amount: Uint128,
+    batch_amount: Uint128,
+) -> Result<Bathces, ContractError> {
+    // Reverts update_batches for the same amount
+    let new_batches = batches
+        .iter()
+        .map(|batch| Batch {
+            amount: batch.amount.sub(amount / batch_amount),
+            release_time: batch.release_time,
+            released: batch.released,
+        })
+        .collect();
+    Ok(new_batches)
+}
+
+// Moves amount out of the unreleased batches, split in proportion to each batch.
+// Returns the non-empty moved batches, rounding dust is taken from the latest batches.
+pub fn split_batches(batches: &mut Bathces, amount: Uint128) -> Result<Bathces, ContractError> {
+    let unreleased: Uint128 = batches
+        .iter()
+        .filter(|batch| !batch.released)
+        .map(|batch| batch.amount)
+        .sum();
+    if amount.is_zero() || amount > unreleased {
+        return Err(ContractError::InvalidTransferAmount {});
+    }
+    let mut shares: Vec<Uint128> = batches
+        .iter()
+        .map(|batch| match batch.released {
+            true => Uint128::zero(),
+            false => batch.amount.multiply_ratio(amount, unreleased),
+        })
+        .collect();
+    let mut remainder = amount - shares.iter().sum::<Uint128>();
+    for (share, batch) in shares.iter_mut().zip(batches.iter()).rev() {
+        if batch.released || remainder.is_zero() {
+            continue;
+        }
+        let extra = remainder.min(batch.amount - *share);
+        *share += extra;
+        remainder -= extra;
+    }
+    let moved = batches
+        .iter_mut()
+        .zip(shares)
+        .filter(|(batch, share)| !batch.released && !share.is_zero())
+        .map(|(batch, share)| {
+            batch.amount -= share;
+            Batch {
+                amount: share,
+                release_time: batch.release_time,
+                released: false,
+            }
+        })
+        .collect();
+    Ok(moved)
+}
+
+// Adds batches into an existing schedule, batches with the same release time and state are summed
+pub fn merge_batches(mut batches: Bathces, new_batches: Bathces) -> Bathces {
+    for new_batch in new_batches {
+        match batches.iter_mut().find(|batch| {
+            batch.release_time == new_batch.release_time && batch.released == new_batch.released
+        }) {
+            Some(batch) => batch.amount += new_batch.amount,
+            None => batches.push(new_batch),
+        }
+    }
+    batches.sort_by_key(|batch| batch.release_time);
+    batches
+}
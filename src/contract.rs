@@ -1,19 +1,57 @@
+use std::collections::BTreeMap;
+
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, to_binary, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128,
+    attr, to_binary, Addr, Api, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env,
+    MessageInfo, Order, Response, StdResult, Storage, Timestamp, Uint128,
 };
-use cw20::Cw20QueryMsg;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{BalanceResponse, Cw20QueryMsg};
 use cw_asset::Asset;
-// use cw2::set_contract_version;
-use crate::batch::{create_batches, update_batches};
+use cw_storage_plus::Bound;
+use cw_utils::{maybe_addr, must_pay};
+use semver::Version;
+
+use crate::batch::{
+    create_batches, is_mature, merge_batches, reduce_batches, split_batches, update_batches,
+};
+use crate::cw721::{mint_msg, position_holder, position_metadata, Metadata};
 use crate::error::ContractError;
+use crate::helpers::{
+    apply_config_change, assert_admin, assert_buy_not_paused, assert_can_change_config,
+    assert_claim_not_paused, assert_no_lien, assert_not_granted, assert_not_tokenized, assert_role,
+    assert_transferable, revenue_payouts, tokens_owed, update_unlocks, validate_revenue_recipients,
+    validate_revenue_vesting, withdrawable_revenue,
+};
+use crate::migrations::{migrate_from_v0_1, v0_1};
 use crate::msg::{
-    ExecuteMsg, InstantiateMsg, QueryConfigResponse, QueryMsg, QueryPositionResponse,
-    QueryStateResponse,
+    AllPositionsResponse, ClaimHistoryResponse, ClaimableResponse, DistributeMaturedResponse,
+    ExecuteMsg, ExportPositionsResponse, GrantsResponse, InstantiateMsg, LendersResponse,
+    ListingsResponse, MigrateMsg, PositionSummary, PositionValueResponse, PurchaseHistoryResponse,
+    QueryAllowlistedResponse, QueryConfigResponse, QueryMsg, QueryPauseResponse,
+    QueryPendingConfigChangesResponse, QueryPositionResponse, QueryRoleHoldersResponse,
+    QueryStateResponse, RevenueVestingResponse, SaleStatsResponse, SalesHistoryBucket,
+    SalesHistoryResponse, SimulateBuyResponse, StakingContract, StakingContractsResponse,
+    UnlockBucket, UnlockScheduleResponse,
 };
-use crate::state::{Batch, Bathces, Config, Position, State, Status, CONFIG, POSITIONS, STATE};
-use cw_utils::{maybe_addr, must_pay};
+use crate::state::{
+    Batch, Bathces, ClaimReceipt, Config, ConfigChange, Grant, Lien, Listing, OtcFee, PauseState,
+    PendingAdmin, PendingConfigChange, PlatformFee, Position, Purchase, ReferralConfig,
+    ReferralCredit, ReferralReward, RevenueRecipient, Role, State, Status, ALLOWLIST, CLAIMS,
+    CLAIM_COUNT, CONFIG, CONFIG_CHANGE_COUNT, DEFAULT_CONFIG_TIMELOCK, GRANTS, LENDERS, LISTINGS,
+    LISTING_COUNT, PAUSE, PENDING_ADMIN, PENDING_CONFIG_CHANGES, POSITIONS, PURCHASES,
+    PURCHASES_BY_PAYMENT, PURCHASE_COUNT, REFERRAL_STATS, REFERRERS, REVENUE_CURSOR, ROLES,
+    SALES_BUCKET_DURATION, SALES_HISTORY, SELLER_LISTINGS, STAKING_CONTRACTS, STAKING_HOOK_OWNER,
+    STATE, UNLOCKS, USER_CLAIMS, USER_PURCHASES,
+};
+
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:launchpad";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -22,22 +60,47 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     let admin = maybe_addr(deps.api, msg.admin)?.unwrap_or_else(|| info.sender.clone());
 
     // check price
     if msg.price.is_zero() {
         return Err(ContractError::InvalidPrice {});
     }
+    let platform_fee = match (msg.platform_fee, msg.platform_fee_collector) {
+        (None, _) => None,
+        (Some(rate), _) if rate.is_zero() => None,
+        (Some(rate), Some(collector)) if rate < Decimal::one() => Some(PlatformFee {
+            rate,
+            collector: deps.api.addr_validate(&collector)?,
+        }),
+        _ => return Err(ContractError::InvalidPlatformFee {}),
+    };
 
     let config = Config {
-        admin,
+        admin: Some(admin.clone()),
         batch_duration: msg.batch_duration,
         batch_amount: msg.batch_amount,
-        revenue_collector: deps.api.addr_validate(&msg.revenue_collector)?,
+        revenue_recipients: validate_revenue_recipients(deps.api, msg.revenue_recipients)?,
+        platform_fee,
+        accumulate_revenue: msg.accumulate_revenue.unwrap_or_default(),
+        revenue_vesting: msg
+            .revenue_vesting
+            .map(validate_revenue_vesting)
+            .transpose()?,
         price: msg.price,
         buy_denom: msg.buy_denom,
         sell_denom: deps.api.addr_validate(&msg.sell_denom)?,
         first_batch_release_time: msg.first_batch_release_time,
+        cooling_off_period: msg.cooling_off_period.unwrap_or_default(),
+        guardian: maybe_addr(deps.api, msg.guardian)?,
+        max_pause_duration: msg.max_pause_duration,
+        allowlist_enabled: msg.allowlist_enabled.unwrap_or_default(),
+        config_timelock: msg.config_timelock.unwrap_or(DEFAULT_CONFIG_TIMELOCK),
+        transfers_enabled: msg.transfers_enabled.unwrap_or(true),
+        position_nft: maybe_addr(deps.api, msg.position_nft)?,
+        otc_fee: None,
+        referral: None,
     };
 
     let state = State {
@@ -45,18 +108,31 @@ pub fn instantiate(
         total_sold: Uint128::zero(),
         // Set status to pending
         status: Status::Pending,
+        pending_revenue: Uint128::zero(),
+        unique_buyers: 0,
+        purchase_count: 0,
+        largest_purchase: Uint128::zero(),
+        total_claimed: Uint128::zero(),
+        total_granted: Uint128::zero(),
+        total_referral_tokens: Uint128::zero(),
+        accumulated_revenue: Uint128::zero(),
+        revenue_withdrawn: Uint128::zero(),
     };
 
     CONFIG.save(deps.storage, &config)?;
     STATE.save(deps.storage, &state)?;
+    PAUSE.save(deps.storage, &PauseState::default())?;
 
     let mut res = Response::default();
     res.attributes = vec![
         attr("action", "instantiate"),
-        attr("admin", config.admin),
+        attr("admin", admin),
         attr("batch_duration", config.batch_duration.to_string()),
         attr("batch_amount", config.batch_amount.to_string()),
-        attr("revenue_collector", config.revenue_collector),
+        attr(
+            "revenue_recipients",
+            recipients_attr(&config.revenue_recipients),
+        ),
         attr("price", config.price.to_string()),
         attr("buy_denom", config.buy_denom),
         attr("sell_denom", config.sell_denom),
@@ -64,6 +140,7 @@ pub fn instantiate(
             "first_batch_release_time",
             config.first_batch_release_time.to_string(),
         ),
+        attr("cooling_off_period", config.cooling_off_period.to_string()),
     ];
     Ok(res)
 }
@@ -76,50 +153,195 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Buy {} => execute_buy(deps, env, info),
+        ExecuteMsg::Buy { referrer } => execute_buy(deps, env, info, referrer),
         ExecuteMsg::UpdateConfig {
-            admin,
             batch_duration,
             batch_amount,
-            revenue_collector,
+            revenue_recipients,
+            accumulate_revenue,
             price,
             buy_denom,
             sell_denom,
+            cooling_off_period,
+            guardian,
+            max_pause_duration,
+            allowlist_enabled,
+            config_timelock,
+            transfers_enabled,
+            position_nft,
         } => execute_update_config(
             deps,
             env,
             info,
-            admin,
             batch_duration,
             batch_amount,
-            revenue_collector,
+            revenue_recipients,
+            accumulate_revenue,
             price,
             buy_denom,
             sell_denom,
+            cooling_off_period,
+            guardian,
+            max_pause_duration,
+            allowlist_enabled,
+            config_timelock,
+            transfers_enabled,
+            position_nft,
         ),
         ExecuteMsg::StartSale {} => execute_start_sale(deps, env, info),
         ExecuteMsg::StartDistribution {} => execute_start_distribution(deps, env, info),
         ExecuteMsg::AdminWithdraw { amount } => execute_admin_withdraw(deps, env, info, amount),
-        ExecuteMsg::Claim {} => execute_claim(deps, env, info),
+        ExecuteMsg::Claim {
+            recipient,
+            token_id,
+        } => execute_claim(deps, env, info, recipient, token_id),
+        ExecuteMsg::MintPositionNft {} => execute_mint_position_nft(deps, info),
+        ExecuteMsg::ClaimFor { owner } => execute_claim_for(deps, env, info, owner),
+        ExecuteMsg::SetAllowClaimFor { allowed } => {
+            execute_set_allow_claim_for(deps, info, allowed)
+        }
+        ExecuteMsg::ClaimAndStake { staking_contract } => {
+            execute_claim_and_stake(deps, env, info, staking_contract)
+        }
+        ExecuteMsg::UpdateStakingContract { address, hook_msg } => {
+            execute_update_staking_contract(deps, info, address, hook_msg)
+        }
+        ExecuteMsg::TransferPosition { recipient, amount } => {
+            execute_transfer_position(deps, env, info, recipient, amount)
+        }
+        ExecuteMsg::SplitPosition { shares } => execute_split_position(deps, env, info, shares),
+        ExecuteMsg::MergeInto { recipient } => execute_merge_into(deps, info, recipient),
+        ExecuteMsg::CreateListing { amount, price } => {
+            execute_create_listing(deps, env, info, amount, price)
+        }
+        ExecuteMsg::CancelListing { id } => execute_cancel_listing(deps, env, info, id),
+        ExecuteMsg::FillListing { id } => execute_fill_listing(deps, env, info, id),
+        ExecuteMsg::UpdateOtcFee { rate, collector } => {
+            execute_update_otc_fee(deps, info, rate, collector)
+        }
+        ExecuteMsg::WithdrawRevenue {} => execute_withdraw_revenue(deps, env),
+        ExecuteMsg::UpdatePlatformFee { rate, collector } => {
+            execute_update_platform_fee(deps, info, rate, collector)
+        }
+        ExecuteMsg::UpdateReferralConfig { rate, reward } => {
+            execute_update_referral_config(deps, info, rate, reward)
+        }
+        ExecuteMsg::GrantAllocation {
+            address,
+            amount,
+            schedule,
+            revocable,
+        } => execute_grant_allocation(deps, env, info, address, amount, schedule, revocable),
+        ExecuteMsg::RevokeAllocation { address } => {
+            execute_revoke_allocation(deps, env, info, address)
+        }
+        ExecuteMsg::UpdateLenders { add, remove } => {
+            execute_update_lenders(deps, info, add, remove)
+        }
+        ExecuteMsg::GrantLien {
+            lender,
+            redirect_claims,
+        } => execute_grant_lien(deps, env, info, lender, redirect_claims),
+        ExecuteMsg::ReleaseLien { owner } => execute_release_lien(deps, info, owner),
+        ExecuteMsg::SeizePosition { owner } => execute_seize_position(deps, env, info, owner),
+        ExecuteMsg::DistributeMatured { start_after, limit } => {
+            execute_distribute_matured(deps, env, info, start_after, limit)
+        }
+        ExecuteMsg::CancelPurchase { purchase_id } => {
+            execute_cancel_purchase(deps, env, info, purchase_id)
+        }
+        ExecuteMsg::ForwardRevenue { limit } => execute_forward_revenue(deps, env, info, limit),
+        ExecuteMsg::Pause {
+            buy,
+            claim,
+            duration,
+        } => execute_pause(deps, env, info, buy, claim, duration),
+        ExecuteMsg::Unpause { buy, claim } => execute_unpause(deps, env, info, buy, claim),
+        ExecuteMsg::ProposeAdmin {
+            address,
+            expires_in,
+        } => execute_propose_admin(deps, env, info, address, expires_in),
+        ExecuteMsg::AcceptAdmin {} => execute_accept_admin(deps, env, info),
+        ExecuteMsg::CancelAdminProposal {} => execute_cancel_admin_proposal(deps, env, info),
+        ExecuteMsg::RenounceAdmin {} => execute_renounce_admin(deps, env, info),
+        ExecuteMsg::GrantRole { role, address } => {
+            execute_grant_role(deps, env, info, role, address)
+        }
+        ExecuteMsg::RevokeRole { role, address } => {
+            execute_revoke_role(deps, env, info, role, address)
+        }
+        ExecuteMsg::UpdateAllowlist { add, remove } => {
+            execute_update_allowlist(deps, env, info, add, remove)
+        }
+        ExecuteMsg::ProposeConfigChange { change } => {
+            execute_propose_config_change(deps, env, info, change)
+        }
+        ExecuteMsg::ExecuteConfigChange { id } => {
+            execute_execute_config_change(deps, env, info, id)
+        }
+        ExecuteMsg::CancelConfigChange { id } => execute_cancel_config_change(deps, env, info, id),
     }
 }
 
-pub fn execute_buy(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+// Outcome of a buy, shared by execute_buy and SimulateBuy
+pub struct BuyQuote {
+    pub amount_paid: Uint128,
+    pub buy_amount: Uint128,
+    pub refund: Uint128,
+    pub effective_price: Decimal,
+    pub old_batches: Bathces,
+    pub position: Position,
+    pub referrer: Option<Addr>,
+}
+
+pub fn quote_buy(
+    deps: Deps,
+    env: &Env,
+    buyer: &Addr,
+    funds: Vec<Coin>,
+    referrer: Option<String>,
+) -> Result<BuyQuote, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let mut state = STATE.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
     // Check if sale is active
     if state.status != Status::Active {
         return Err(ContractError::SaleNotActive {});
     }
+    assert_buy_not_paused(deps.storage, env)?;
+    if config.allowlist_enabled && !ALLOWLIST.has(deps.storage, buyer) {
+        return Err(ContractError::NotAllowlisted {});
+    }
 
-    let amount_paid = must_pay(&info, &config.buy_denom)?;
-    let buy_amount = Decimal::from_ratio(amount_paid, Uint128::from(1u128))
+    let info = MessageInfo {
+        sender: buyer.clone(),
+        funds,
+    };
+    let funds_sent = must_pay(&info, &config.buy_denom)?;
+    let buy_amount = Decimal::from_ratio(funds_sent, Uint128::from(1u128))
         .checked_div(config.price)
         //price or amount can not be zero so its safe to unwrap
         .unwrap();
     // floor buy_amount
     let buy_amount = buy_amount.to_uint_floor();
-    let position = POSITIONS.may_load(deps.storage, info.sender.clone())?;
+    if buy_amount.is_zero() {
+        return Err(ContractError::ZeroBuyAmount {});
+    }
+    // Only charge for whole tokens, the rest is refunded
+    let amount_paid = (Decimal::from_ratio(buy_amount, Uint128::from(1u128)) * config.price)
+        .to_uint_ceil()
+        .min(funds_sent);
+    let refund = funds_sent - amount_paid;
+
+    assert_not_granted(deps.storage, buyer)?;
+    let referrer = maybe_addr(deps.api, referrer)?;
+    if referrer.as_ref() == Some(buyer) {
+        return Err(ContractError::SelfReferral {});
+    }
+    let position = POSITIONS.may_load(deps.storage, buyer.clone())?;
+    let old_batches = position
+        .as_ref()
+        .map(|position| position.batches.clone())
+        .unwrap_or_default();
     let new_position = match position {
         Some(mut position) => {
             // if position does exist, add buy_amount to total_bought and total_paid and update batches
@@ -127,6 +349,7 @@ pub fn execute_buy(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Respons
             position.total_paid += amount_paid;
             let new_batches = update_batches(position.batches, buy_amount, config.batch_amount)?;
             position.batches = new_batches;
+            position.update_average_price();
             position
         }
 
@@ -138,54 +361,314 @@ pub fn execute_buy(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Respons
                 config.first_batch_release_time,
             )?;
             Position {
-                address: info.sender.clone(),
+                address: buyer.clone(),
                 total_bought: buy_amount,
                 total_paid: amount_paid,
                 total_claimed: Uint128::zero(),
                 price: config.price,
                 timestamp: env.block.time,
+                average_price: Decimal::from_ratio(amount_paid, buy_amount),
+                allow_claim_for: false,
+                tokenized: false,
+                lien: None,
                 batches,
             }
         }
     };
-    POSITIONS.save(deps.storage, info.sender, &new_position)?;
+
+    Ok(BuyQuote {
+        amount_paid,
+        buy_amount,
+        refund,
+        effective_price: Decimal::from_ratio(amount_paid, buy_amount),
+        old_batches,
+        position: new_position,
+        referrer,
+    })
+}
+
+pub fn execute_buy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    referrer: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    let BuyQuote {
+        amount_paid,
+        buy_amount,
+        refund,
+        old_batches,
+        position: new_position,
+        referrer,
+        ..
+    } = quote_buy(
+        deps.as_ref(),
+        &env,
+        &info.sender,
+        info.funds.clone(),
+        referrer,
+    )?;
+
+    // referral rewards alone do not make a buyer
+    let old_position = POSITIONS.may_load(deps.storage, info.sender.clone())?;
+    let new_buyer = old_position
+        .as_ref()
+        .is_none_or(|position| position.total_paid.is_zero());
+    if new_buyer {
+        state.unique_buyers += 1;
+    }
+    // the first referrer stays recorded, also after cancelled purchases
+    if new_buyer && !REFERRERS.has(deps.storage, &info.sender) {
+        if let Some(referrer) = &referrer {
+            REFERRERS.save(deps.storage, &info.sender, referrer)?;
+            REFERRAL_STATS.update(deps.storage, referrer, |stats| -> StdResult<_> {
+                let mut stats = stats.unwrap_or_default();
+                stats.referrals += 1;
+                Ok(stats)
+            })?;
+        }
+    }
+    POSITIONS.save(deps.storage, info.sender.clone(), &new_position)?;
+    update_unlocks(deps.storage, &old_batches, &new_position.batches)?;
+    let referral = match REFERRERS.may_load(deps.storage, &info.sender)? {
+        Some(referrer) => credit_referral(
+            deps.storage,
+            &mut state,
+            &config,
+            env.block.time,
+            referrer,
+            amount_paid,
+            buy_amount,
+        )?,
+        None => None,
+    };
+    // record purchase as a receipt, it can be cancelled during the cooling-off period
+    let purchase_id = PURCHASE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    PURCHASE_COUNT.save(deps.storage, &purchase_id)?;
+    PURCHASES.save(
+        deps.storage,
+        purchase_id,
+        &Purchase {
+            id: purchase_id,
+            buyer: info.sender.clone(),
+            amount_paid,
+            amount_bought: buy_amount,
+            price: config.price,
+            timestamp: env.block.time,
+            cancelled: false,
+            referral: referral.clone(),
+        },
+    )?;
+    USER_PURCHASES.save(deps.storage, (&info.sender, purchase_id), &Empty {})?;
+    PURCHASES_BY_PAYMENT.save(deps.storage, (amount_paid.u128(), purchase_id), &Empty {})?;
     // update state
     state.total_sold += buy_amount;
     state.total_revenue += amount_paid;
+    state.purchase_count += 1;
+    state.largest_purchase = state.largest_purchase.max(amount_paid);
+    SALES_HISTORY.update(
+        deps.storage,
+        sales_bucket_start(env.block.time),
+        |bucket| -> StdResult<_> {
+            let mut bucket = bucket.unwrap_or_default();
+            bucket.revenue += amount_paid;
+            bucket.tokens_sold += buy_amount;
+            bucket.purchases += 1;
+            Ok(bucket)
+        },
+    )?;
+
+    let mut res = Response::default();
+    if config.cooling_off_period == 0 {
+        // Pay out revenue, less the referrer's payment reward
+        let payment_reward = referral_payment(&config, &referral);
+        res = res.add_messages(pay_revenue(
+            &config,
+            &mut state,
+            amount_paid - payment_reward,
+        )?);
+        if let Some(referral) = referral.filter(|_| !payment_reward.is_zero()) {
+            let reward_asset = Asset::native(config.buy_denom.clone(), payment_reward);
+            res = res.add_message(reward_asset.transfer_msg(referral.referrer)?);
+        }
+    } else {
+        // Hold revenue until the cooling-off period has passed
+        state.pending_revenue += amount_paid;
+    }
     STATE.save(deps.storage, &state)?;
-    // Send revenue to revenue_collector
-    let revenue_asset = Asset::native(config.buy_denom, amount_paid);
-    let revenue_msg = revenue_asset.transfer_msg(config.revenue_collector)?;
+    if !refund.is_zero() {
+        let refund_asset = Asset::native(config.buy_denom, refund);
+        res = res.add_message(refund_asset.transfer_msg(info.sender)?);
+    }
 
-    let res = Response::default()
-        .add_message(revenue_msg)
+    let res = res
         .add_attribute("action", "buy")
         .add_attribute("amount_paid", amount_paid)
-        .add_attribute("buy_amount", buy_amount);
+        .add_attribute("buy_amount", buy_amount)
+        .add_attribute("purchase_id", purchase_id.to_string())
+        .add_attribute("refund", refund);
 
     Ok(res)
 }
+// Credits the referrer of a purchase, payment rewards are paid out with the revenue
+fn credit_referral(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    config: &Config,
+    time: Timestamp,
+    referrer: Addr,
+    amount_paid: Uint128,
+    buy_amount: Uint128,
+) -> Result<Option<ReferralCredit>, ContractError> {
+    let referral = match &config.referral {
+        Some(referral) => referral,
+        None => return Ok(None),
+    };
+    let reward = match referral.reward {
+        ReferralReward::Payment => amount_paid * referral.rate,
+        // granted referrers can not receive tokens, their purchases earn no reward
+        ReferralReward::Tokens if GRANTS.has(storage, &referrer) => Uint128::zero(),
+        ReferralReward::Tokens => {
+            // whole batches only so the referrer's batches add up to its total
+            let reward = buy_amount * referral.rate;
+            reward - reward % config.batch_amount
+        }
+    };
+    REFERRAL_STATS.update(storage, &referrer, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.purchases += 1;
+        stats.volume += amount_paid;
+        stats.rewards += reward;
+        Ok(stats)
+    })?;
+    // zero rewards are recorded as well so a cancellation can undo the stats
+    if referral.reward == ReferralReward::Tokens && !reward.is_zero() {
+        let position = match POSITIONS.may_load(storage, referrer.clone())? {
+            Some(mut position) => {
+                let old_batches = position.batches.clone();
+                position.total_bought += reward;
+                position.batches = update_batches(position.batches, reward, config.batch_amount)?;
+                position.update_average_price();
+                update_unlocks(storage, &old_batches, &position.batches)?;
+                position
+            }
+            None => {
+                let batches = create_batches(
+                    config.batch_duration,
+                    config.batch_amount,
+                    reward,
+                    config.first_batch_release_time,
+                )?;
+                update_unlocks(storage, &[], &batches)?;
+                Position {
+                    address: referrer.clone(),
+                    total_bought: reward,
+                    total_paid: Uint128::zero(),
+                    total_claimed: Uint128::zero(),
+                    price: Decimal::zero(),
+                    timestamp: time,
+                    average_price: Decimal::zero(),
+                    allow_claim_for: false,
+                    tokenized: false,
+                    lien: None,
+                    batches,
+                }
+            }
+        };
+        POSITIONS.save(storage, referrer.clone(), &position)?;
+        state.total_referral_tokens += reward;
+    }
+    Ok(Some(ReferralCredit { referrer, reward }))
+}
+
+// Part of a purchase's payment owed to its referrer
+fn referral_payment(config: &Config, referral: &Option<ReferralCredit>) -> Uint128 {
+    match (&config.referral, referral) {
+        (
+            Some(ReferralConfig {
+                reward: ReferralReward::Payment,
+                ..
+            }),
+            Some(referral),
+        ) => referral.reward,
+        _ => Uint128::zero(),
+    }
+}
+
+// Sends revenue to the platform and the recipients, or holds it for WithdrawRevenue
+fn pay_revenue(
+    config: &Config,
+    state: &mut State,
+    amount: Uint128,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    if config.accumulate_revenue || config.revenue_vesting.is_some() {
+        state.accumulated_revenue += amount;
+        return Ok(vec![]);
+    }
+    revenue_payouts(config, amount)
+        .into_iter()
+        .map(|(recipient, amount)| {
+            Ok(Asset::native(config.buy_denom.clone(), amount).transfer_msg(recipient)?)
+        })
+        .collect()
+}
+
+fn recipients_attr(recipients: &[RevenueRecipient]) -> String {
+    recipients
+        .iter()
+        .map(|recipient| format!("{}:{}", recipient.address, recipient.weight))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn sales_bucket_start(time: Timestamp) -> u64 {
+    time.seconds() - time.seconds() % SALES_BUCKET_DURATION
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn execute_update_config(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    admin: Option<String>,
     batch_duration: Option<Uint128>,
     batch_amount: Option<Uint128>,
-    revenue_collector: Option<String>,
+    revenue_recipients: Option<Vec<(String, Decimal)>>,
+    accumulate_revenue: Option<bool>,
     price: Option<Decimal>,
     buy_denom: Option<String>,
     sell_denom: Option<String>,
+    cooling_off_period: Option<u64>,
+    guardian: Option<String>,
+    max_pause_duration: Option<u64>,
+    allowlist_enabled: Option<bool>,
+    config_timelock: Option<u64>,
+    transfers_enabled: Option<bool>,
+    position_nft: Option<String>,
 ) -> Result<Response, ContractError> {
     let mut config = CONFIG.load(deps.storage)?;
     let state = STATE.load(deps.storage)?;
 
-    if info.sender != config.admin {
-        return Err(ContractError::Unauthorized {});
+    // Treasurer can update revenue recipients, everything else is admin only
+    if revenue_recipients.is_some() {
+        assert_role(deps.storage, &config, &info.sender, Role::Treasurer)?;
     }
-    if let Some(admin) = admin {
-        config.admin = deps.api.addr_validate(&admin)?;
+    let admin_fields_set = batch_duration.is_some()
+        || batch_amount.is_some()
+        || accumulate_revenue.is_some()
+        || price.is_some()
+        || buy_denom.is_some()
+        || sell_denom.is_some()
+        || cooling_off_period.is_some()
+        || guardian.is_some()
+        || max_pause_duration.is_some()
+        || allowlist_enabled.is_some()
+        || config_timelock.is_some()
+        || transfers_enabled.is_some()
+        || position_nft.is_some();
+    if admin_fields_set || revenue_recipients.is_none() {
+        assert_admin(&config, &info.sender)?;
     }
 
     if let Some(batch_duration) = batch_duration {
@@ -202,15 +685,27 @@ pub fn execute_update_config(
             return Err(ContractError::SaleNotPending {});
         }
     }
-    if let Some(revenue_collector) = revenue_collector {
-        config.revenue_collector = deps.api.addr_validate(&revenue_collector)?;
+    // Once the sale has started these go through ProposeConfigChange
+    if let Some(revenue_recipients) = revenue_recipients {
+        if state.status == Status::Pending {
+            config.revenue_recipients = validate_revenue_recipients(deps.api, revenue_recipients)?;
+        } else {
+            return Err(ContractError::SaleNotPending {});
+        }
+    }
+    if let Some(accumulate_revenue) = accumulate_revenue {
+        if state.status == Status::Pending {
+            config.accumulate_revenue = accumulate_revenue;
+        } else {
+            return Err(ContractError::SaleNotPending {});
+        }
     }
     if let Some(price) = price {
-        // check price
-        if price.is_zero() {
-            return Err(ContractError::InvalidPrice {});
+        if state.status == Status::Pending {
+            apply_config_change(deps.api, &mut config, ConfigChange::Price { price })?;
+        } else {
+            return Err(ContractError::SaleNotPending {});
         }
-        config.price = price;
     }
     if let Some(buy_denom) = buy_denom {
         if state.status == Status::Pending {
@@ -226,18 +721,57 @@ pub fn execute_update_config(
             return Err(ContractError::SaleNotPending {});
         }
     }
+    if let Some(cooling_off_period) = cooling_off_period {
+        if state.status == Status::Pending {
+            config.cooling_off_period = cooling_off_period;
+        } else {
+            return Err(ContractError::SaleNotPending {});
+        }
+    }
+    if let Some(guardian) = guardian {
+        config.guardian = Some(deps.api.addr_validate(&guardian)?);
+    }
+    if let Some(max_pause_duration) = max_pause_duration {
+        config.max_pause_duration = Some(max_pause_duration);
+    }
+    if let Some(allowlist_enabled) = allowlist_enabled {
+        config.allowlist_enabled = allowlist_enabled;
+    }
+    if let Some(config_timelock) = config_timelock {
+        if state.status == Status::Pending {
+            config.config_timelock = config_timelock;
+        } else {
+            return Err(ContractError::SaleNotPending {});
+        }
+    }
+    if let Some(transfers_enabled) = transfers_enabled {
+        config.transfers_enabled = transfers_enabled;
+    }
+    if let Some(position_nft) = position_nft {
+        if state.status == Status::Pending {
+            config.position_nft = Some(deps.api.addr_validate(&position_nft)?);
+        } else {
+            return Err(ContractError::SaleNotPending {});
+        }
+    }
 
     CONFIG.save(deps.storage, &config)?;
     let mut res = Response::default();
     res.attributes = vec![
         attr("action", "update_config"),
-        attr("admin", config.admin),
         attr("batch_duration", config.batch_duration.to_string()),
         attr("batch_amount", config.batch_amount.to_string()),
-        attr("revenue_collector", config.revenue_collector),
+        attr(
+            "revenue_recipients",
+            recipients_attr(&config.revenue_recipients),
+        ),
+        attr("accumulate_revenue", config.accumulate_revenue.to_string()),
         attr("price", config.price.to_string()),
         attr("buy_denom", config.buy_denom),
         attr("sell_denom", config.sell_denom),
+        attr("cooling_off_period", config.cooling_off_period.to_string()),
+        attr("allowlist_enabled", config.allowlist_enabled.to_string()),
+        attr("transfers_enabled", config.transfers_enabled.to_string()),
     ];
     Ok(res)
 }
@@ -249,9 +783,7 @@ pub fn execute_start_sale(
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
     let mut state = STATE.load(deps.storage)?;
-    if info.sender != config.admin {
-        return Err(ContractError::Unauthorized {});
-    }
+    assert_role(deps.storage, &config, &info.sender, Role::Operator)?;
     if state.status != Status::Pending {
         return Err(ContractError::SaleNotPending {});
     }
@@ -269,9 +801,7 @@ pub fn execute_start_distribution(
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
     let mut state = STATE.load(deps.storage)?;
-    if info.sender != config.admin {
-        return Err(ContractError::Unauthorized {});
-    }
+    assert_role(deps.storage, &config, &info.sender, Role::Operator)?;
     if state.status != Status::Active {
         return Err(ContractError::SaleNotActive {});
     }
@@ -290,21 +820,18 @@ pub fn execute_admin_withdraw(
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
     let state = STATE.load(deps.storage)?;
-    if info.sender != config.admin {
-        return Err(ContractError::Unauthorized {});
-    }
+    assert_role(deps.storage, &config, &info.sender, Role::Treasurer)?;
     if state.status != Status::Distribution {
         return Err(ContractError::SaleNotDistribution {});
     }
     // check cw20 contract balance
-    let cw20_balance: Uint128 = deps.querier.query_wasm_smart(
+    let cw20_balance: BalanceResponse = deps.querier.query_wasm_smart(
         config.sell_denom.clone(),
         &Cw20QueryMsg::Balance {
             address: env.contract.address.to_string(),
         },
     )?;
-
-    if cw20_balance < amount {
+    if cw20_balance.balance.saturating_sub(tokens_owed(&state)) < amount {
         return Err(ContractError::InsufficientBalance {});
     }
 
@@ -325,95 +852,2177 @@ pub fn execute_claim(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    recipient: Option<String>,
+    token_id: Option<String>,
+) -> Result<Response, ContractError> {
+    let recipient = maybe_addr(deps.api, recipient)?.unwrap_or_else(|| info.sender.clone());
+    let owner = match token_id {
+        // Token id is the address the position is stored under
+        Some(token_id) => {
+            let config = CONFIG.load(deps.storage)?;
+            let owner = deps.api.addr_validate(&token_id)?;
+            let position = POSITIONS.load(deps.storage, owner.clone())?;
+            if !position.tokenized
+                || position_holder(deps.querier, &config, &position)? != info.sender
+            {
+                return Err(ContractError::Unauthorized {});
+            }
+            owner
+        }
+        None => {
+            let position = POSITIONS.load(deps.storage, info.sender.clone())?;
+            assert_not_tokenized(&position)?;
+            info.sender
+        }
+    };
+    claim(deps, env, owner, recipient, None, "claim")
+}
+
+pub fn execute_mint_position_nft(
+    deps: DepsMut,
+    info: MessageInfo,
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
     let state = STATE.load(deps.storage)?;
-    if state.status != Status::Distribution {
-        return Err(ContractError::SaleNotDistribution {});
-    }
-    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
-    // check if is there any batch to claim
-    let mature_claims: Vec<Batch> = position
+    let nft = config
+        .position_nft
         .clone()
-        .batches
-        .into_iter()
-        .filter(|batch| batch.release_time < env.block.time)
-        .collect();
+        .ok_or(ContractError::PositionNftNotEnabled {})?;
+    // Schedules are final once distribution has started, the NFT trades like a transfer
+    assert_transferable(&config, &state)?;
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&position)?;
+    assert_no_lien(&position)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    // cancelled listings could not return to a tokenized position
+    if SELLER_LISTINGS
+        .prefix(&info.sender)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some()
+    {
+        return Err(ContractError::OpenListings {});
+    }
+    position.tokenized = true;
+    position.allow_claim_for = false;
+    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
 
-    if mature_claims.is_empty() {
-        return Err(ContractError::NoMatureClaims {});
+    let res = Response::default()
+        .add_attributes(vec![
+            attr("action", "mint_position_nft"),
+            attr("token_id", info.sender),
+        ])
+        .add_message(mint_msg(&nft, &position)?);
+    Ok(res)
+}
+
+pub fn execute_claim_for(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let position = POSITIONS.load(deps.storage, owner.clone())?;
+    assert_not_tokenized(&position)?;
+    if !position.allow_claim_for {
+        return Err(ContractError::ClaimForNotAllowed {});
     }
-    // remove mature claims from position
-    position
-        .batches
-        .retain(|batch| batch.release_time >= env.block.time);
+    claim(deps, env, owner.clone(), owner, None, "claim_for")
+}
 
-    // save position
+pub fn execute_claim_and_stake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    staking_contract: String,
+) -> Result<Response, ContractError> {
+    let staking_contract = deps.api.addr_validate(&staking_contract)?;
+    assert_not_tokenized(&POSITIONS.load(deps.storage, info.sender.clone())?)?;
+    let hook_msg = STAKING_CONTRACTS
+        .may_load(deps.storage, &staking_contract)?
+        .ok_or(ContractError::StakingContractNotAllowed {})?;
+    let hook_msg = Binary::from(
+        hook_msg
+            .replace(STAKING_HOOK_OWNER, info.sender.as_str())
+            .as_bytes(),
+    );
+    claim(
+        deps,
+        env,
+        info.sender,
+        staking_contract,
+        Some(hook_msg),
+        "claim_and_stake",
+    )
+}
 
-    // calculate total amount to claim
-    let total_amount: Uint128 = mature_claims
-        .into_iter()
-        .map(|batch| batch.amount)
-        .sum::<Uint128>();
-    position.total_claimed += total_amount;
+fn claim(
+    deps: DepsMut,
+    env: Env,
+    owner: Addr,
+    recipient: Addr,
+    // Tokens are sent to the recipient contract with this message instead of transferred
+    hook_msg: Option<Binary>,
+    action: &str,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    if state.status != Status::Distribution {
+        return Err(ContractError::SaleNotDistribution {});
+    }
+    assert_claim_not_paused(deps.storage, &env)?;
+    let position = POSITIONS.load(deps.storage, owner.clone())?;
+    // Claims of a position under lien go to the lender or are blocked
+    let (recipient, hook_msg) = match &position.lien {
+        Some(lien) if lien.redirect_claims => (lien.lender.clone(), None),
+        Some(_) => return Err(ContractError::LienActive {}),
+        None => (recipient, hook_msg),
+    };
+    let receipt = release_matured(
+        deps.storage,
+        &mut state,
+        env.block.time,
+        position,
+        recipient.clone(),
+    )?;
+    STATE.save(deps.storage, &state)?;
 
-    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
-    let claim_asset = Asset::cw20(config.sell_denom, total_amount);
-    let claim_msg = claim_asset.transfer_msg(info.sender)?;
+    let claim_asset = Asset::cw20(config.sell_denom, receipt.amount);
+    let claim_msg = match hook_msg {
+        Some(hook_msg) => claim_asset.send_msg(recipient.clone(), hook_msg)?,
+        None => claim_asset.transfer_msg(recipient.clone())?,
+    };
 
     let res = Response::default()
         .add_attributes(vec![
-            attr("action", "claim"),
-            attr("amount", total_amount.to_string()),
+            attr("action", action),
+            attr("owner", owner),
+            attr("recipient", recipient),
+            attr("amount", receipt.amount.to_string()),
+            attr("claim_id", receipt.id.to_string()),
         ])
         .add_message(claim_msg);
 
     Ok(res)
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::QueryConfig {} => to_binary(&query_config(deps)?),
-        QueryMsg::QueryState {} => to_binary(&query_state(deps)?),
-        QueryMsg::QueryPosition { address } => to_binary(&query_position(deps, address)?),
+pub fn execute_distribute_matured(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.storage, &config, &info.sender, Role::Operator)?;
+    let mut state = STATE.load(deps.storage)?;
+    if state.status != Status::Distribution {
+        return Err(ContractError::SaleNotDistribution {});
+    }
+    assert_claim_not_paused(deps.storage, &env)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let positions = range_positions(deps.as_ref(), start_after, Some(limit))?;
+    let next_start_after = if positions.len() == limit as usize {
+        positions
+            .last()
+            .map(|position| position.address.to_string())
+    } else {
+        None
+    };
+
+    let mut messages = vec![];
+    let mut distributed = Uint128::zero();
+    for position in positions {
+        if !position
+            .batches
+            .iter()
+            .any(|batch| is_mature(batch, env.block.time))
+        {
+            continue;
+        }
+        // same accounting as execute_claim so released batches can not be claimed again
+        let owner = match &position.lien {
+            Some(lien) if lien.redirect_claims => lien.lender.clone(),
+            Some(_) => continue,
+            None => position_holder(deps.querier, &config, &position)?,
+        };
+        let receipt = release_matured(
+            deps.storage,
+            &mut state,
+            env.block.time,
+            position,
+            owner.clone(),
+        )?;
+        distributed += receipt.amount;
+        let claim_asset = Asset::cw20(config.sell_denom.clone(), receipt.amount);
+        messages.push(claim_asset.transfer_msg(owner)?);
     }
+    STATE.save(deps.storage, &state)?;
+
+    let data = DistributeMaturedResponse {
+        distributed,
+        recipients: messages.len() as u32,
+        next_start_after: next_start_after.clone(),
+    };
+    let res = Response::default()
+        .add_attributes(vec![
+            attr("action", "distribute_matured"),
+            attr("distributed", distributed.to_string()),
+            attr("recipients", messages.len().to_string()),
+            attr("next_start_after", next_start_after.unwrap_or_default()),
+        ])
+        .add_messages(messages)
+        .set_data(to_binary(&data)?);
+    Ok(res)
 }
 
-pub fn query_config(deps: Deps) -> StdResult<QueryConfigResponse> {
+pub fn execute_update_staking_contract(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    hook_msg: Option<String>,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    Ok(QueryConfigResponse {
-        admin: config.admin.to_string(),
-        batch_duration: config.batch_duration,
-        batch_amount: config.batch_amount,
-        revenue_collector: config.revenue_collector.to_string(),
-        price: config.price,
-        buy_denom: config.buy_denom,
-        sell_denom: config.sell_denom.to_string(),
-        first_batch_release_time: config.first_batch_release_time,
-    })
+    assert_admin(&config, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    match &hook_msg {
+        Some(hook_msg) => STAKING_CONTRACTS.save(deps.storage, &address, hook_msg)?,
+        None => STAKING_CONTRACTS.remove(deps.storage, &address),
+    }
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_staking_contract"),
+        attr("address", address),
+        attr("allowed", hook_msg.is_some().to_string()),
+    ]);
+    Ok(res)
 }
 
-pub fn query_state(deps: Deps) -> StdResult<QueryStateResponse> {
+pub fn execute_transfer_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
     let state = STATE.load(deps.storage)?;
-    Ok(QueryStateResponse {
-        status: state.status.to_string(),
-        total_sold: state.total_sold,
-        total_revenue: state.total_revenue,
-    })
+    assert_transferable(&config, &state)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    if recipient == info.sender {
+        return Err(ContractError::CannotTransferToSelf {});
+    }
+
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&position)?;
+    assert_no_lien(&position)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    let amount = amount.unwrap_or_else(|| position.unclaimed());
+    let (moved_batches, moved_paid) = debit_position(&mut position, amount)?;
+    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
+    credit_position(
+        deps.storage,
+        env.block.time,
+        &recipient,
+        amount,
+        moved_paid,
+        moved_batches,
+    )?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "transfer_position"),
+        attr("sender", info.sender),
+        attr("recipient", recipient),
+        attr("amount", amount.to_string()),
+    ]);
+    Ok(res)
 }
 
-pub fn query_position(deps: Deps, address: String) -> StdResult<QueryPositionResponse> {
-    let addr = deps.api.addr_validate(&address)?;
+pub fn execute_split_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    shares: Vec<(String, Uint128)>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    if shares.is_empty() {
+        return Err(ContractError::InvalidTransferAmount {});
+    }
 
-    let position = POSITIONS.load(deps.storage, addr)?;
-    Ok(QueryPositionResponse {
-        address: position.address.to_string(),
-        total_bought: position.total_bought,
-        total_paid: position.total_paid,
-        total_claimed: position.total_claimed,
-        price: position.price,
-        timestamp: position.timestamp,
-        batches: position.batches,
-    })
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&position)?;
+    assert_no_lien(&position)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    // validate every share before moving anything
+    let mut total = Uint128::zero();
+    let mut recipients = vec![];
+    for (recipient, amount) in &shares {
+        let recipient = deps.api.addr_validate(recipient)?;
+        if recipient == info.sender {
+            return Err(ContractError::CannotTransferToSelf {});
+        }
+        if amount.is_zero() {
+            return Err(ContractError::InvalidTransferAmount {});
+        }
+        total += *amount;
+        recipients.push((recipient, *amount));
+    }
+    if total > position.unclaimed() {
+        return Err(ContractError::InvalidTransferAmount {});
+    }
+
+    for (recipient, amount) in recipients {
+        let (moved_batches, moved_paid) = debit_position(&mut position, amount)?;
+        credit_position(
+            deps.storage,
+            env.block.time,
+            &recipient,
+            amount,
+            moved_paid,
+            moved_batches,
+        )?;
+    }
+    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "split_position"),
+        attr("sender", info.sender),
+        attr("recipients", shares.len().to_string()),
+        attr("amount", total.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_merge_into(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    if recipient == info.sender {
+        return Err(ContractError::CannotTransferToSelf {});
+    }
+
+    let source = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&source)?;
+    assert_no_lien(&source)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    // grants keep their own schedule and tokenized or pledged positions can not take more tokens
+    assert_not_granted(deps.storage, &recipient)?;
+    let target = match POSITIONS.may_load(deps.storage, recipient.clone())? {
+        Some(mut target) => {
+            assert_not_tokenized(&target)?;
+            assert_no_lien(&target)?;
+            target.total_bought += source.total_bought;
+            target.total_paid += source.total_paid;
+            target.total_claimed += source.total_claimed;
+            target.batches = merge_batches(target.batches, source.batches.clone());
+            target.update_average_price();
+            target
+        }
+        None => Position {
+            address: recipient.clone(),
+            allow_claim_for: false,
+            tokenized: false,
+            lien: None,
+            ..source.clone()
+        },
+    };
+    POSITIONS.save(deps.storage, recipient.clone(), &target)?;
+    POSITIONS.remove(deps.storage, info.sender.clone());
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "merge_into"),
+        attr("sender", info.sender),
+        attr("recipient", recipient),
+        attr("amount", source.unclaimed().to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_create_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    price: Decimal,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    if price.is_zero() {
+        return Err(ContractError::InvalidPrice {});
+    }
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&position)?;
+    assert_no_lien(&position)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    // escrow the listed share until the listing is filled or cancelled
+    let (batches, seller_paid) = debit_position(&mut position, amount)?;
+    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
+
+    let id = LISTING_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    LISTING_COUNT.save(deps.storage, &id)?;
+    let listing = Listing {
+        id,
+        seller: info.sender.clone(),
+        amount,
+        price,
+        batches,
+        seller_paid,
+        created_at: env.block.time,
+    };
+    LISTINGS.save(deps.storage, id, &listing)?;
+    SELLER_LISTINGS.save(deps.storage, (&info.sender, id), &Empty {})?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "create_listing"),
+        attr("listing_id", id.to_string()),
+        attr("amount", amount.to_string()),
+        attr("price", price.to_string()),
+    ]);
+    Ok(res)
+}
+
+fn remove_listing(storage: &mut dyn Storage, listing: &Listing) {
+    LISTINGS.remove(storage, listing.id);
+    SELLER_LISTINGS.remove(storage, (&listing.seller, listing.id));
+}
+
+pub fn execute_cancel_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let listing = LISTINGS.load(deps.storage, id)?;
+    if info.sender != listing.seller {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_listing(deps.storage, &listing);
+    credit_position(
+        deps.storage,
+        env.block.time,
+        &listing.seller,
+        listing.amount,
+        listing.seller_paid,
+        listing.batches,
+    )?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "cancel_listing"),
+        attr("listing_id", id.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_fill_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    let listing = LISTINGS.load(deps.storage, id)?;
+    if info.sender == listing.seller {
+        return Err(ContractError::CannotFillOwnListing {});
+    }
+    if let Some(position) = POSITIONS.may_load(deps.storage, info.sender.clone())? {
+        assert_not_tokenized(&position)?;
+    }
+
+    let cost =
+        (Decimal::from_ratio(listing.amount, Uint128::from(1u128)) * listing.price).to_uint_ceil();
+    let funds_sent = must_pay(&info, &config.buy_denom)?;
+    if funds_sent < cost {
+        return Err(ContractError::InsufficientFunds { required: cost });
+    }
+    let refund = funds_sent - cost;
+    let fee = config
+        .otc_fee
+        .as_ref()
+        .map(|fee| cost * fee.rate)
+        .unwrap_or_default();
+
+    remove_listing(deps.storage, &listing);
+    credit_position(
+        deps.storage,
+        env.block.time,
+        &info.sender,
+        listing.amount,
+        cost,
+        listing.batches,
+    )?;
+
+    let mut res = Response::default().add_attributes(vec![
+        attr("action", "fill_listing"),
+        attr("listing_id", id.to_string()),
+        attr("buyer", info.sender.clone()),
+        attr("cost", cost.to_string()),
+        attr("fee", fee.to_string()),
+    ]);
+    if !(cost - fee).is_zero() {
+        let payment = Asset::native(config.buy_denom.clone(), cost - fee);
+        res = res.add_message(payment.transfer_msg(listing.seller)?);
+    }
+    if let Some(otc_fee) = config.otc_fee.filter(|_| !fee.is_zero()) {
+        let fee_asset = Asset::native(config.buy_denom.clone(), fee);
+        res = res.add_message(fee_asset.transfer_msg(otc_fee.collector)?);
+    }
+    if !refund.is_zero() {
+        let refund_asset = Asset::native(config.buy_denom, refund);
+        res = res.add_message(refund_asset.transfer_msg(info.sender)?);
+    }
+    Ok(res)
+}
+
+pub fn execute_update_otc_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    rate: Decimal,
+    collector: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    if rate >= Decimal::one() {
+        return Err(ContractError::InvalidOtcFee {});
+    }
+    let collector = deps.api.addr_validate(&collector)?;
+    config.otc_fee = if rate.is_zero() {
+        None
+    } else {
+        Some(OtcFee {
+            rate,
+            collector: collector.clone(),
+        })
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_otc_fee"),
+        attr("rate", rate.to_string()),
+        attr("collector", collector),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_update_referral_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    rate: Decimal,
+    reward: ReferralReward,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    // Purchases keep the reward they were made with
+    if state.status != Status::Pending {
+        return Err(ContractError::SaleNotPending {});
+    }
+    if rate >= Decimal::one() {
+        return Err(ContractError::InvalidReferralRate {});
+    }
+    config.referral = if rate.is_zero() {
+        None
+    } else {
+        Some(ReferralConfig {
+            rate,
+            reward: reward.clone(),
+        })
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_referral_config"),
+        attr("rate", rate.to_string()),
+        attr("reward", format!("{:?}", reward).to_lowercase()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_grant_allocation(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    amount: Uint128,
+    schedule: Option<Vec<UnlockBucket>>,
+    revocable: bool,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    if POSITIONS.has(deps.storage, address.clone()) {
+        return Err(ContractError::PositionExists {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidGrant {});
+    }
+    let mut batches: Bathces = match schedule {
+        Some(schedule) => schedule
+            .into_iter()
+            .map(|unlock| Batch {
+                amount: unlock.amount,
+                release_time: unlock.release_time,
+                released: false,
+            })
+            .collect(),
+        None => {
+            let mut batches = create_batches(
+                config.batch_duration,
+                config.batch_amount,
+                amount,
+                config.first_batch_release_time,
+            )?;
+            // rounding dust unlocks with the last batch
+            let batched: Uint128 = batches.iter().map(|batch| batch.amount).sum();
+            if let Some(last) = batches.last_mut() {
+                last.amount += amount - batched;
+            }
+            batches
+        }
+    };
+    batches.retain(|batch| !batch.amount.is_zero());
+    batches.sort_by_key(|batch| batch.release_time);
+    if batches.iter().map(|batch| batch.amount).sum::<Uint128>() != amount {
+        return Err(ContractError::InvalidGrant {});
+    }
+    // the contract has to hold the grant on top of what it already owes
+    let state = STATE.load(deps.storage)?;
+    let cw20_balance: BalanceResponse = deps.querier.query_wasm_smart(
+        config.sell_denom.clone(),
+        &Cw20QueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    if cw20_balance.balance.saturating_sub(tokens_owed(&state)) < amount {
+        return Err(ContractError::InsufficientBalance {});
+    }
+
+    let position = Position {
+        address: address.clone(),
+        total_bought: amount,
+        total_paid: Uint128::zero(),
+        total_claimed: Uint128::zero(),
+        price: Decimal::zero(),
+        timestamp: env.block.time,
+        average_price: Decimal::zero(),
+        allow_claim_for: false,
+        tokenized: false,
+        lien: None,
+        batches,
+    };
+    POSITIONS.save(deps.storage, address.clone(), &position)?;
+    update_unlocks(deps.storage, &[], &position.batches)?;
+    GRANTS.save(
+        deps.storage,
+        &address,
+        &Grant {
+            address: address.clone(),
+            amount,
+            granted_at: env.block.time,
+            revocable,
+            revoked_at: None,
+            clawed_back: Uint128::zero(),
+        },
+    )?;
+    STATE.update(deps.storage, |mut state| -> StdResult<_> {
+        state.total_granted += amount;
+        Ok(state)
+    })?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "grant_allocation"),
+        attr("address", address),
+        attr("amount", amount.to_string()),
+        attr("revocable", revocable.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_revoke_allocation(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.storage, &config, &info.sender, Role::Treasurer)?;
+    let mut state = STATE.load(deps.storage)?;
+    let address = deps.api.addr_validate(&address)?;
+    let mut grant = GRANTS
+        .may_load(deps.storage, &address)?
+        .filter(|grant| grant.revocable)
+        .ok_or(ContractError::NotRevocable {})?;
+    if grant.revoked_at.is_some() {
+        return Err(ContractError::GrantRevoked {});
+    }
+
+    let time = env.block.time;
+    let mut position = POSITIONS.load(deps.storage, address.clone())?;
+    let mut res = Response::default();
+    // Vested tokens go to the holder, they can only be paid out once distribution has started
+    let mut vested = Uint128::zero();
+    if state.status == Status::Distribution
+        && position.batches.iter().any(|batch| is_mature(batch, time))
+    {
+        let receipt = release_matured(deps.storage, &mut state, time, position, address.clone())?;
+        vested = receipt.amount;
+        let vested_asset = Asset::cw20(config.sell_denom.clone(), vested);
+        res = res.add_message(vested_asset.transfer_msg(address.clone())?);
+        position = POSITIONS.load(deps.storage, address.clone())?;
+    }
+    // Batches that have not unlocked yet are clawed back
+    let old_batches = position.batches.clone();
+    let unvested: Uint128 = old_batches
+        .iter()
+        .filter(|batch| !batch.released && !is_mature(batch, time))
+        .map(|batch| batch.amount)
+        .sum();
+    position
+        .batches
+        .retain(|batch| batch.released || is_mature(batch, time));
+    position.total_bought -= unvested;
+    POSITIONS.save(deps.storage, address.clone(), &position)?;
+    update_unlocks(deps.storage, &old_batches, &position.batches)?;
+    state.total_granted -= unvested;
+    STATE.save(deps.storage, &state)?;
+    grant.revoked_at = Some(time);
+    grant.clawed_back = unvested;
+    GRANTS.save(deps.storage, &address, &grant)?;
+
+    if !unvested.is_zero() {
+        let unvested_asset = Asset::cw20(config.sell_denom, unvested);
+        res = res.add_message(unvested_asset.transfer_msg(info.sender)?);
+    }
+    let res = res.add_attributes(vec![
+        attr("action", "revoke_allocation"),
+        attr("address", address),
+        attr("vested", vested.to_string()),
+        attr("clawed_back", unvested.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_update_lenders(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    for address in add.iter() {
+        let address = deps.api.addr_validate(address)?;
+        LENDERS.save(deps.storage, &address, &Empty {})?;
+    }
+    // Existing liens of removed lenders stay until they are released or seized
+    for address in remove.iter() {
+        let address = deps.api.addr_validate(address)?;
+        LENDERS.remove(deps.storage, &address);
+    }
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_lenders"),
+        attr("added", add.len().to_string()),
+        attr("removed", remove.len().to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_grant_lien(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    lender: String,
+    redirect_claims: bool,
+) -> Result<Response, ContractError> {
+    // a seized position moves like a transfer
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    let lender = deps.api.addr_validate(&lender)?;
+    if !LENDERS.has(deps.storage, &lender) {
+        return Err(ContractError::LenderNotApproved {});
+    }
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    assert_not_tokenized(&position)?;
+    assert_no_lien(&position)?;
+    assert_not_granted(deps.storage, &info.sender)?;
+    position.lien = Some(Lien {
+        lender: lender.clone(),
+        redirect_claims,
+        granted_at: env.block.time,
+    });
+    POSITIONS.save(deps.storage, info.sender.clone(), &position)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "grant_lien"),
+        attr("owner", info.sender),
+        attr("lender", lender),
+        attr("redirect_claims", redirect_claims.to_string()),
+    ]);
+    Ok(res)
+}
+
+// Loads a position under lien of the sender
+fn load_lien_position(
+    storage: &dyn Storage,
+    api: &dyn Api,
+    lender: &Addr,
+    owner: &str,
+) -> Result<Position, ContractError> {
+    let owner = api.addr_validate(owner)?;
+    let position = POSITIONS.load(storage, owner)?;
+    match &position.lien {
+        Some(lien) if &lien.lender == lender => Ok(position),
+        Some(_) => Err(ContractError::Unauthorized {}),
+        None => Err(ContractError::NoLien {}),
+    }
+}
+
+pub fn execute_release_lien(
+    deps: DepsMut,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let mut position = load_lien_position(deps.storage, deps.api, &info.sender, &owner)?;
+    position.lien = None;
+    POSITIONS.save(deps.storage, position.address.clone(), &position)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "release_lien"),
+        attr("owner", position.address),
+        attr("lender", info.sender),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_seize_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    assert_transferable(&config, &state)?;
+    let mut position = load_lien_position(deps.storage, deps.api, &info.sender, &owner)?;
+    position.lien = None;
+    let amount = position.unclaimed();
+    if !amount.is_zero() {
+        let (batches, paid) = debit_position(&mut position, amount)?;
+        credit_position(
+            deps.storage,
+            env.block.time,
+            &info.sender,
+            amount,
+            paid,
+            batches,
+        )?;
+    }
+    POSITIONS.save(deps.storage, position.address.clone(), &position)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "seize_position"),
+        attr("owner", position.address),
+        attr("lender", info.sender),
+        attr("amount", amount.to_string()),
+    ]);
+    Ok(res)
+}
+
+// Takes amount of unclaimed tokens out of the position with the matching share of total_paid
+fn debit_position(
+    position: &mut Position,
+    amount: Uint128,
+) -> Result<(Bathces, Uint128), ContractError> {
+    let batches = split_batches(&mut position.batches, amount)?;
+    let paid = position
+        .total_paid
+        .multiply_ratio(amount, position.total_bought);
+    position.total_bought -= amount;
+    position.total_paid -= paid;
+    position.update_average_price();
+    Ok((batches, paid))
+}
+
+// Merges tokens into the owner's schedule, creating the position if there is none
+fn credit_position(
+    storage: &mut dyn Storage,
+    time: Timestamp,
+    owner: &Addr,
+    amount: Uint128,
+    paid: Uint128,
+    batches: Bathces,
+) -> Result<(), ContractError> {
+    assert_not_granted(storage, owner)?;
+    let position = match POSITIONS.may_load(storage, owner.clone())? {
+        Some(mut position) => {
+            // tokens added to a tokenized position would follow the NFT owner instead
+            assert_not_tokenized(&position)?;
+            position.total_bought += amount;
+            position.total_paid += paid;
+            position.batches = merge_batches(position.batches, batches);
+            position.update_average_price();
+            position
+        }
+        None => Position {
+            address: owner.clone(),
+            total_bought: amount,
+            total_paid: paid,
+            total_claimed: Uint128::zero(),
+            price: Decimal::from_ratio(paid, amount),
+            timestamp: time,
+            average_price: Decimal::from_ratio(paid, amount),
+            allow_claim_for: false,
+            tokenized: false,
+            lien: None,
+            batches,
+        },
+    };
+    POSITIONS.save(storage, owner.clone(), &position)?;
+    Ok(())
+}
+
+pub fn execute_set_allow_claim_for(
+    deps: DepsMut,
+    info: MessageInfo,
+    allowed: bool,
+) -> Result<Response, ContractError> {
+    let mut position = POSITIONS.load(deps.storage, info.sender.clone())?;
+    position.allow_claim_for = allowed;
+    POSITIONS.save(deps.storage, info.sender, &position)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "set_allow_claim_for"),
+        attr("allowed", allowed.to_string()),
+    ]);
+    Ok(res)
+}
+
+// Marks mature batches of the position released, updates claimed totals and records a claim receipt
+fn release_matured(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    time: Timestamp,
+    mut position: Position,
+    recipient: Addr,
+) -> Result<ClaimReceipt, ContractError> {
+    let mut amount = Uint128::zero();
+    let mut release_times = vec![];
+    for batch in position.batches.iter_mut() {
+        if is_mature(batch, time) {
+            batch.released = true;
+            amount += batch.amount;
+            release_times.push(batch.release_time);
+        }
+    }
+    if release_times.is_empty() {
+        return Err(ContractError::NoMatureClaims {});
+    }
+    position.total_claimed += amount;
+    state.total_claimed += amount;
+    POSITIONS.save(storage, position.address.clone(), &position)?;
+
+    let id = CLAIM_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    CLAIM_COUNT.save(storage, &id)?;
+    let receipt = ClaimReceipt {
+        id,
+        owner: position.address,
+        recipient,
+        amount,
+        release_times,
+        timestamp: time,
+    };
+    CLAIMS.save(storage, id, &receipt)?;
+    USER_CLAIMS.save(storage, (&receipt.owner, id), &Empty {})?;
+    Ok(receipt)
+}
+
+pub fn execute_cancel_purchase(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    purchase_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    // Tokens can not be taken back once distribution has started
+    if state.status != Status::Active {
+        return Err(ContractError::SaleNotActive {});
+    }
+    let mut purchase = PURCHASES.load(deps.storage, purchase_id)?;
+    if info.sender != purchase.buyer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if purchase.cancelled {
+        return Err(ContractError::PurchaseCancelled {});
+    }
+    if env.block.time >= purchase.timestamp.plus_seconds(config.cooling_off_period) {
+        return Err(ContractError::CoolingOffPeriodPassed {});
+    }
+
+    // remove purchased tokens from position
+    let mut position = POSITIONS.load(deps.storage, purchase.buyer.clone())?;
+    position.total_bought -= purchase.amount_bought;
+    position.total_paid -= purchase.amount_paid;
+    position.update_average_price();
+    let old_batches = position.batches.clone();
+    position.batches = reduce_batches(
+        position.batches,
+        purchase.amount_bought,
+        config.batch_amount,
+    )?;
+    POSITIONS.save(deps.storage, purchase.buyer.clone(), &position)?;
+    update_unlocks(deps.storage, &old_batches, &position.batches)?;
+
+    // update state
+    state.total_sold -= purchase.amount_bought;
+    state.total_revenue -= purchase.amount_paid;
+    state.pending_revenue -= purchase.amount_paid;
+    state.purchase_count -= 1;
+    // the buyer's only purchase is gone
+    if position.total_paid.is_zero() {
+        state.unique_buyers -= 1;
+    }
+    PURCHASES_BY_PAYMENT.remove(deps.storage, (purchase.amount_paid.u128(), purchase_id));
+    if purchase.amount_paid == state.largest_purchase {
+        state.largest_purchase = PURCHASES_BY_PAYMENT
+            .keys(deps.storage, None, None, Order::Descending)
+            .next()
+            .transpose()?
+            .map_or(Uint128::zero(), |(amount, _)| Uint128::from(amount));
+    }
+    STATE.save(deps.storage, &state)?;
+    SALES_HISTORY.update(
+        deps.storage,
+        sales_bucket_start(purchase.timestamp),
+        |bucket| -> StdResult<_> {
+            let mut bucket = bucket.unwrap_or_default();
+            bucket.revenue -= purchase.amount_paid;
+            bucket.tokens_sold -= purchase.amount_bought;
+            bucket.purchases -= 1;
+            Ok(bucket)
+        },
+    )?;
+
+    // take back the referrer's reward, payment rewards are not paid out yet
+    if let Some(referral) = &purchase.referral {
+        REFERRAL_STATS.update(deps.storage, &referral.referrer, |stats| -> StdResult<_> {
+            let mut stats = stats.unwrap_or_default();
+            stats.purchases -= 1;
+            stats.volume -= purchase.amount_paid;
+            stats.rewards -= referral.reward;
+            Ok(stats)
+        })?;
+        if !referral.reward.is_zero() && referral_payment(&config, &purchase.referral).is_zero() {
+            let mut position = POSITIONS.load(deps.storage, referral.referrer.clone())?;
+            let old_batches = position.batches.clone();
+            position.total_bought -= referral.reward;
+            position.batches =
+                reduce_batches(position.batches, referral.reward, config.batch_amount)?;
+            POSITIONS.save(deps.storage, referral.referrer.clone(), &position)?;
+            update_unlocks(deps.storage, &old_batches, &position.batches)?;
+            STATE.update(deps.storage, |mut state| -> StdResult<_> {
+                state.total_referral_tokens -= referral.reward;
+                Ok(state)
+            })?;
+        }
+    }
+    purchase.cancelled = true;
+    PURCHASES.save(deps.storage, purchase_id, &purchase)?;
+
+    // Refund payment to buyer
+    let refund_asset = Asset::native(config.buy_denom, purchase.amount_paid);
+    let refund_msg = refund_asset.transfer_msg(purchase.buyer)?;
+
+    let res = Response::default()
+        .add_attributes(vec![
+            attr("action", "cancel_purchase"),
+            attr("purchase_id", purchase_id.to_string()),
+            attr("amount_refunded", purchase.amount_paid.to_string()),
+            attr("amount_cancelled", purchase.amount_bought.to_string()),
+        ])
+        .add_message(refund_msg);
+
+    Ok(res)
+}
+
+pub fn execute_forward_revenue(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let cursor = REVENUE_CURSOR.may_load(deps.storage)?.unwrap_or_default();
+
+    // Purchases are stored in time order so walk them until one is still in its cooling-off period
+    let purchases = PURCHASES
+        .range(
+            deps.storage,
+            Some(Bound::exclusive(cursor)),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<(u64, Purchase)>>>()?;
+    let mut new_cursor = cursor;
+    let mut amount = Uint128::zero();
+    let mut rewards: BTreeMap<Addr, Uint128> = BTreeMap::new();
+    for (id, purchase) in purchases {
+        let window_end = purchase.timestamp.plus_seconds(config.cooling_off_period);
+        if state.status != Status::Distribution && env.block.time < window_end {
+            break;
+        }
+        if !purchase.cancelled {
+            amount += purchase.amount_paid;
+            // rewards of instantly forwarded purchases were paid with the buy
+            let payment_reward = referral_payment(&config, &purchase.referral);
+            if let Some(referral) = purchase
+                .referral
+                .filter(|_| config.cooling_off_period > 0 && !payment_reward.is_zero())
+            {
+                *rewards.entry(referral.referrer).or_default() += payment_reward;
+            }
+        }
+        new_cursor = id;
+    }
+    // a page of cancelled purchases still moves the cursor
+    if new_cursor == cursor {
+        return Err(ContractError::NoRevenueToForward {});
+    }
+    // revenue of instantly forwarded purchases is never pending
+    let amount = amount.min(state.pending_revenue);
+    REVENUE_CURSOR.save(deps.storage, &new_cursor)?;
+    state.pending_revenue -= amount;
+
+    // referrers are paid their share of the forwarded payments
+    let total_rewards: Uint128 = rewards.values().sum();
+    let revenue_msgs = pay_revenue(&config, &mut state, amount - total_rewards)?;
+    STATE.save(deps.storage, &state)?;
+    let mut res = Response::default()
+        .add_attributes(vec![
+            attr("action", "forward_revenue"),
+            attr("amount", amount.to_string()),
+            attr("cursor", new_cursor.to_string()),
+            attr("referral_rewards", total_rewards.to_string()),
+        ])
+        .add_messages(revenue_msgs);
+    for (referrer, reward) in rewards {
+        let reward_asset = Asset::native(config.buy_denom.clone(), reward);
+        res = res.add_message(reward_asset.transfer_msg(referrer)?);
+    }
+
+    Ok(res)
+}
+
+pub fn execute_withdraw_revenue(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    let amount = withdrawable_revenue(&config, &state, env.block.time);
+    if amount.is_zero() {
+        return Err(ContractError::NoRevenueToWithdraw {});
+    }
+    state.accumulated_revenue -= amount;
+    state.revenue_withdrawn += amount;
+    STATE.save(deps.storage, &state)?;
+
+    let mut res = Response::default().add_attributes(vec![
+        attr("action", "withdraw_revenue"),
+        attr("amount", amount.to_string()),
+    ]);
+    for (recipient, amount) in revenue_payouts(&config, amount) {
+        let revenue_asset = Asset::native(config.buy_denom.clone(), amount);
+        res = res.add_message(revenue_asset.transfer_msg(recipient)?);
+    }
+    Ok(res)
+}
+
+pub fn execute_update_platform_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    rate: Decimal,
+    collector: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let current_rate = match &config.platform_fee {
+        Some(fee) if fee.collector == info.sender => fee.rate,
+        _ => return Err(ContractError::Unauthorized {}),
+    };
+    if rate >= Decimal::one() {
+        return Err(ContractError::InvalidPlatformFee {});
+    }
+    // Revenue recipients can rely on the fee once the sale has started
+    if state.status != Status::Pending && rate > current_rate {
+        return Err(ContractError::SaleNotPending {});
+    }
+    let collector = deps.api.addr_validate(&collector)?;
+    config.platform_fee = if rate.is_zero() {
+        None
+    } else {
+        Some(PlatformFee {
+            rate,
+            collector: collector.clone(),
+        })
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_platform_fee"),
+        attr("rate", rate.to_string()),
+        attr("collector", collector),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_pause(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    buy: bool,
+    claim: bool,
+    duration: Option<u64>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let is_guardian = config.guardian.as_ref() == Some(&info.sender);
+    if !is_guardian {
+        assert_admin(&config, &info.sender)?;
+    }
+    // Guardian pauses always expire when max_pause_duration is set
+    let duration = match (is_guardian, config.max_pause_duration) {
+        (true, Some(max)) => match duration {
+            Some(duration) if duration > max => {
+                return Err(ContractError::InvalidPauseDuration { max })
+            }
+            Some(duration) => Some(duration),
+            None => Some(max),
+        },
+        _ => duration,
+    };
+
+    let mut pause = PAUSE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .active_at(env.block.time);
+    // only the flags being paused get the new expiry
+    let expires_at = duration.map(|duration| env.block.time.plus_seconds(duration));
+    let guardian_max = config.max_pause_duration.filter(|_| is_guardian);
+    if buy {
+        pause_flag(
+            &mut pause.buy_paused,
+            &mut pause.buy_expires_at,
+            &mut pause.buy_cooldown_until,
+            expires_at,
+            guardian_max,
+            env.block.time,
+        )?;
+    }
+    if claim {
+        pause_flag(
+            &mut pause.claim_paused,
+            &mut pause.claim_expires_at,
+            &mut pause.claim_cooldown_until,
+            expires_at,
+            guardian_max,
+            env.block.time,
+        )?;
+    }
+    PAUSE.save(deps.storage, &pause)?;
+
+    let expiry = |expires_at: Option<Timestamp>| {
+        expires_at.map_or("never".to_string(), |time| time.to_string())
+    };
+    let res = Response::default().add_attributes(vec![
+        attr("action", "pause"),
+        attr("buy_paused", pause.buy_paused.to_string()),
+        attr("claim_paused", pause.claim_paused.to_string()),
+        attr("buy_expires_at", expiry(pause.buy_expires_at)),
+        attr("claim_expires_at", expiry(pause.claim_expires_at)),
+    ]);
+    Ok(res)
+}
+
+// Guardian pauses are followed by a cooldown as long as the pause itself,
+// so the guardian can not keep a flag paused by pausing it again
+fn pause_flag(
+    paused: &mut bool,
+    expires_at: &mut Option<Timestamp>,
+    cooldown_until: &mut Option<Timestamp>,
+    new_expires_at: Option<Timestamp>,
+    guardian_max: Option<u64>,
+    time: Timestamp,
+) -> Result<(), ContractError> {
+    if let Some(max) = guardian_max {
+        if cooldown_until.is_some_and(|cooldown_until| time < cooldown_until) {
+            return Err(ContractError::PauseCooldown {});
+        }
+        // guardian pauses always expire, new_expires_at is set
+        *cooldown_until = new_expires_at.map(|expires_at| expires_at.plus_seconds(max));
+        // an admin pause without expiry is not shortened
+        if *paused && expires_at.is_none() {
+            return Ok(());
+        }
+    }
+    *paused = true;
+    *expires_at = new_expires_at;
+    Ok(())
+}
+
+pub fn execute_unpause(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    buy: bool,
+    claim: bool,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if config.guardian.as_ref() != Some(&info.sender) {
+        assert_admin(&config, &info.sender)?;
+    }
+    let mut pause = PAUSE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .active_at(env.block.time);
+    if buy {
+        pause.buy_paused = false;
+        pause.buy_expires_at = None;
+    }
+    if claim {
+        pause.claim_paused = false;
+        pause.claim_expires_at = None;
+    }
+    PAUSE.save(deps.storage, &pause)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "unpause"),
+        attr("buy_paused", pause.buy_paused.to_string()),
+        attr("claim_paused", pause.claim_paused.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_propose_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    expires_in: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    let pending_admin = PendingAdmin {
+        address: deps.api.addr_validate(&address)?,
+        expires_at: env.block.time.plus_seconds(expires_in),
+    };
+    PENDING_ADMIN.save(deps.storage, &pending_admin)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "propose_admin"),
+        attr("pending_admin", pending_admin.address),
+        attr("expires_at", pending_admin.expires_at.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_accept_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let pending_admin = PENDING_ADMIN
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoAdminProposal {})?;
+    if info.sender != pending_admin.address {
+        return Err(ContractError::Unauthorized {});
+    }
+    if env.block.time >= pending_admin.expires_at {
+        return Err(ContractError::AdminProposalExpired {});
+    }
+    config.admin = Some(pending_admin.address);
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "accept_admin"),
+        attr("admin", info.sender),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_cancel_admin_proposal(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    if PENDING_ADMIN.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoAdminProposal {});
+    }
+    PENDING_ADMIN.remove(deps.storage);
+
+    let res = Response::default().add_attribute("action", "cancel_admin_proposal");
+    Ok(res)
+}
+
+pub fn execute_renounce_admin(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    // The sale has to reach distribution first or tokens would be locked for good
+    if STATE.load(deps.storage)?.status != Status::Distribution {
+        return Err(ContractError::SaleNotDistribution {});
+    }
+    // Nobody can update the sale after this
+    config.admin = None;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+    // roles are handed out by the admin and go with it
+    ROLES.clear(deps.storage);
+
+    let res = Response::default().add_attribute("action", "renounce_admin");
+    Ok(res)
+}
+
+pub fn execute_grant_role(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.save(deps.storage, (&role.to_string(), &address), &Empty {})?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "grant_role"),
+        attr("role", role.to_string()),
+        attr("address", address),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_revoke_role(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.remove(deps.storage, (&role.to_string(), &address));
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "revoke_role"),
+        attr("role", role.to_string()),
+        attr("address", address),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_update_allowlist(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.storage, &config, &info.sender, Role::AllowlistManager)?;
+    for address in add.iter() {
+        let address = deps.api.addr_validate(address)?;
+        ALLOWLIST.save(deps.storage, &address, &Empty {})?;
+    }
+    for address in remove.iter() {
+        let address = deps.api.addr_validate(address)?;
+        ALLOWLIST.remove(deps.storage, &address);
+    }
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "update_allowlist"),
+        attr("added", add.len().to_string()),
+        attr("removed", remove.len().to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_propose_config_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    change: ConfigChange,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_can_change_config(deps.storage, &config, &info.sender, &change)?;
+    // Validate the change now so it can not fail once the timelock has passed
+    apply_config_change(deps.api, &mut config.clone(), change.clone())?;
+
+    let id = CONFIG_CHANGE_COUNT
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        + 1;
+    CONFIG_CHANGE_COUNT.save(deps.storage, &id)?;
+    let pending_change = PendingConfigChange {
+        id,
+        change,
+        proposed_at: env.block.time,
+        executable_at: env.block.time.plus_seconds(config.config_timelock),
+    };
+    PENDING_CONFIG_CHANGES.save(deps.storage, id, &pending_change)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "propose_config_change"),
+        attr("id", id.to_string()),
+        attr("executable_at", pending_change.executable_at.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_execute_config_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let pending_change = PENDING_CONFIG_CHANGES.load(deps.storage, id)?;
+    assert_can_change_config(deps.storage, &config, &info.sender, &pending_change.change)?;
+    if env.block.time < pending_change.executable_at {
+        return Err(ContractError::TimelockNotExpired {
+            executable_at: pending_change.executable_at,
+        });
+    }
+    apply_config_change(deps.api, &mut config, pending_change.change)?;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_CONFIG_CHANGES.remove(deps.storage, id);
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "execute_config_change"),
+        attr("id", id.to_string()),
+        attr(
+            "revenue_recipients",
+            recipients_attr(&config.revenue_recipients),
+        ),
+        attr("price", config.price.to_string()),
+    ]);
+    Ok(res)
+}
+
+pub fn execute_cancel_config_change(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let pending_change = PENDING_CONFIG_CHANGES.load(deps.storage, id)?;
+    assert_can_change_config(deps.storage, &config, &info.sender, &pending_change.change)?;
+    PENDING_CONFIG_CHANGES.remove(deps.storage, id);
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "cancel_config_change"),
+        attr("id", id.to_string()),
+    ]);
+    Ok(res)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    // v0.1 did not store a contract version
+    let previous = get_contract_version(deps.storage).unwrap_or(cw2::ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: v0_1::VERSION.to_string(),
+    });
+    if previous.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: previous.contract,
+            previous_version: previous.version,
+        });
+    }
+    let previous_version: Version = previous.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    // refuse downgrades
+    if previous_version > new_version {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: previous.contract,
+            previous_version: previous.version,
+        });
+    }
+
+    if previous_version <= v0_1::VERSION.parse::<Version>()? {
+        migrate_from_v0_1(deps.storage)?;
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let res = Response::default().add_attributes(vec![
+        attr("action", "migrate"),
+        attr("previous_version", previous.version),
+        attr("new_version", CONTRACT_VERSION),
+    ]);
+    Ok(res)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::QueryConfig {} => to_binary(&query_config(deps)?),
+        QueryMsg::QueryState {} => to_binary(&query_state(deps)?),
+        QueryMsg::QueryPosition { address } => to_binary(&query_position(deps, address)?),
+        QueryMsg::QueryPurchase { purchase_id } => {
+            to_binary(&PURCHASES.load(deps.storage, purchase_id)?)
+        }
+        QueryMsg::PurchaseHistory {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_purchase_history(deps, address, start_after, limit)?),
+        QueryMsg::PositionNftMetadata { token_id } => {
+            to_binary(&query_position_nft_metadata(deps, token_id)?)
+        }
+        QueryMsg::PositionValue { address } => {
+            to_binary(&query_position_value(deps, env, address)?)
+        }
+        QueryMsg::Lenders { start_after, limit } => {
+            to_binary(&query_lenders(deps, start_after, limit)?)
+        }
+        QueryMsg::ReferralStats { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            to_binary(
+                &REFERRAL_STATS
+                    .may_load(deps.storage, &address)?
+                    .unwrap_or_default(),
+            )
+        }
+        QueryMsg::RevenueVesting {} => to_binary(&query_revenue_vesting(deps, env)?),
+        QueryMsg::Grants { start_after, limit } => {
+            to_binary(&query_grants(deps, start_after, limit)?)
+        }
+        QueryMsg::Listing { id } => to_binary(&LISTINGS.load(deps.storage, id)?),
+        QueryMsg::Listings { start_after, limit } => {
+            to_binary(&query_listings(deps, start_after, limit)?)
+        }
+        QueryMsg::ListingsBySeller {
+            seller,
+            start_after,
+            limit,
+        } => to_binary(&query_listings_by_seller(deps, seller, start_after, limit)?),
+        QueryMsg::StakingContracts { start_after, limit } => {
+            to_binary(&query_staking_contracts(deps, start_after, limit)?)
+        }
+        QueryMsg::ClaimHistory {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_claim_history(deps, address, start_after, limit)?),
+        QueryMsg::QueryPause {} => to_binary(&query_pause(deps, env)?),
+        QueryMsg::QueryRoleHolders {
+            role,
+            start_after,
+            limit,
+        } => to_binary(&query_role_holders(deps, role, start_after, limit)?),
+        QueryMsg::QueryAllowlisted { address } => to_binary(&query_allowlisted(deps, address)?),
+        QueryMsg::QueryPendingConfigChanges { start_after, limit } => {
+            to_binary(&query_pending_config_changes(deps, start_after, limit)?)
+        }
+        QueryMsg::AllPositions { start_after, limit } => {
+            to_binary(&query_all_positions(deps, start_after, limit)?)
+        }
+        QueryMsg::ExportPositions { start_after, limit } => {
+            to_binary(&query_export_positions(deps, start_after, limit)?)
+        }
+        QueryMsg::Claimable { address, at_time } => {
+            to_binary(&query_claimable(deps, env, address, at_time)?)
+        }
+        QueryMsg::UnlockSchedule { start, end } => {
+            to_binary(&query_unlock_schedule(deps, start, end)?)
+        }
+        QueryMsg::SimulateBuy {
+            buyer,
+            funds,
+            referrer,
+        } => to_binary(&query_simulate_buy(deps, env, buyer, funds, referrer)?),
+        QueryMsg::SaleStats {} => to_binary(&query_sale_stats(deps)?),
+        QueryMsg::SalesHistory { start_after, limit } => {
+            to_binary(&query_sales_history(deps, start_after, limit)?)
+        }
+    }
+}
+
+pub fn query_config(deps: Deps) -> StdResult<QueryConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pending_admin = PENDING_ADMIN.may_load(deps.storage)?;
+    Ok(QueryConfigResponse {
+        admin: config.admin.map(|admin| admin.to_string()),
+        pending_admin: pending_admin
+            .as_ref()
+            .map(|pending_admin| pending_admin.address.to_string()),
+        pending_admin_expires_at: pending_admin.map(|pending_admin| pending_admin.expires_at),
+        batch_duration: config.batch_duration,
+        batch_amount: config.batch_amount,
+        revenue_recipients: config.revenue_recipients,
+        platform_fee: config.platform_fee,
+        accumulate_revenue: config.accumulate_revenue,
+        revenue_vesting: config.revenue_vesting,
+        price: config.price,
+        buy_denom: config.buy_denom,
+        sell_denom: config.sell_denom.to_string(),
+        first_batch_release_time: config.first_batch_release_time,
+        cooling_off_period: config.cooling_off_period,
+        guardian: config.guardian.map(|guardian| guardian.to_string()),
+        max_pause_duration: config.max_pause_duration,
+        allowlist_enabled: config.allowlist_enabled,
+        config_timelock: config.config_timelock,
+        transfers_enabled: config.transfers_enabled,
+        position_nft: config.position_nft.map(|nft| nft.to_string()),
+        otc_fee: config.otc_fee,
+        referral: config.referral,
+    })
+}
+
+pub fn query_state(deps: Deps) -> StdResult<QueryStateResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(QueryStateResponse {
+        status: state.status.to_string(),
+        total_sold: state.total_sold,
+        total_revenue: state.total_revenue,
+        pending_revenue: state.pending_revenue,
+        accumulated_revenue: state.accumulated_revenue,
+        total_granted: state.total_granted,
+        total_referral_tokens: state.total_referral_tokens,
+    })
+}
+
+pub fn query_position(deps: Deps, address: String) -> StdResult<QueryPositionResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+
+    let position = POSITIONS.load(deps.storage, addr)?;
+    Ok(QueryPositionResponse {
+        address: position.address.to_string(),
+        total_bought: position.total_bought,
+        total_paid: position.total_paid,
+        total_claimed: position.total_claimed,
+        price: position.price,
+        timestamp: position.timestamp,
+        average_price: position.average_price,
+        allow_claim_for: position.allow_claim_for,
+        tokenized: position.tokenized,
+        lien: position.lien,
+        batches: position.batches,
+    })
+}
+
+fn range_positions(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<Position>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let start = start_after.map(Bound::exclusive);
+    POSITIONS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, position)| position))
+        .collect()
+}
+
+pub fn query_all_positions(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<AllPositionsResponse> {
+    let positions = range_positions(deps, start_after, limit)?
+        .into_iter()
+        .map(|position| PositionSummary {
+            address: position.address.to_string(),
+            total_bought: position.total_bought,
+            total_paid: position.total_paid,
+            total_claimed: position.total_claimed,
+            unclaimed: position.unclaimed(),
+            price: position.price,
+            average_price: position.average_price,
+        })
+        .collect();
+    Ok(AllPositionsResponse { positions })
+}
+
+pub fn query_export_positions(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ExportPositionsResponse> {
+    let positions = range_positions(deps, start_after, limit)?;
+    Ok(ExportPositionsResponse { positions })
+}
+
+pub fn query_claimable(
+    deps: Deps,
+    env: Env,
+    address: String,
+    at_time: Option<Timestamp>,
+) -> StdResult<ClaimableResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let time = at_time.unwrap_or(env.block.time);
+    let position = POSITIONS.load(deps.storage, addr)?;
+
+    // same release-time filter as execute_claim
+    let amount = position
+        .batches
+        .iter()
+        .filter(|batch| is_mature(batch, time))
+        .map(|batch| batch.amount)
+        .sum();
+    let next_unlock_time = position
+        .batches
+        .iter()
+        .filter(|batch| !batch.released && batch.release_time >= time)
+        .map(|batch| batch.release_time)
+        .min();
+    let next_unlock_amount = position
+        .batches
+        .iter()
+        .filter(|batch| !batch.released && Some(batch.release_time) == next_unlock_time)
+        .map(|batch| batch.amount)
+        .sum();
+    let fully_vested_time = position
+        .batches
+        .iter()
+        .map(|batch| batch.release_time)
+        .max();
+
+    Ok(ClaimableResponse {
+        address,
+        at_time: time,
+        amount,
+        next_unlock_time,
+        next_unlock_amount,
+        fully_vested_time,
+    })
+}
+
+pub fn query_unlock_schedule(
+    deps: Deps,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> StdResult<UnlockScheduleResponse> {
+    let start = start.map(|start| Bound::inclusive(start.nanos()));
+    let end = end.map(|end| Bound::exclusive(end.nanos()));
+    let unlocks = UNLOCKS
+        .range(deps.storage, start, end, Order::Ascending)
+        .filter(|item| !matches!(item, Ok((_, amount)) if amount.is_zero()))
+        .map(|item| {
+            item.map(|(release_time, amount)| UnlockBucket {
+                release_time: Timestamp::from_nanos(release_time),
+                amount,
+            })
+        })
+        .collect::<StdResult<Vec<UnlockBucket>>>()?;
+    let total = unlocks.iter().map(|unlock| unlock.amount).sum();
+    Ok(UnlockScheduleResponse { unlocks, total })
+}
+
+pub fn query_simulate_buy(
+    deps: Deps,
+    env: Env,
+    buyer: String,
+    funds: Vec<Coin>,
+    referrer: Option<String>,
+) -> StdResult<SimulateBuyResponse> {
+    let buyer = deps.api.addr_validate(&buyer)?;
+    let res = match quote_buy(deps, &env, &buyer, funds, referrer) {
+        Ok(quote) => SimulateBuyResponse {
+            buy_amount: quote.buy_amount,
+            amount_paid: quote.amount_paid,
+            effective_price: Some(quote.effective_price),
+            refund: quote.refund,
+            batches: quote.position.batches,
+            error: None,
+        },
+        // Buy would be rejected with this error
+        Err(err) => SimulateBuyResponse {
+            buy_amount: Uint128::zero(),
+            amount_paid: Uint128::zero(),
+            effective_price: None,
+            refund: Uint128::zero(),
+            batches: vec![],
+            error: Some(err.to_string()),
+        },
+    };
+    Ok(res)
+}
+
+pub fn query_sale_stats(deps: Deps) -> StdResult<SaleStatsResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(SaleStatsResponse {
+        total_revenue: state.total_revenue,
+        total_sold: state.total_sold,
+        unique_buyers: state.unique_buyers,
+        purchase_count: state.purchase_count,
+        largest_purchase: state.largest_purchase,
+        total_claimed: state.total_claimed,
+    })
+}
+
+pub fn query_sales_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<SalesHistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let buckets = SALES_HISTORY
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(start, bucket)| SalesHistoryBucket {
+                start,
+                revenue: bucket.revenue,
+                tokens_sold: bucket.tokens_sold,
+                purchases: bucket.purchases,
+            })
+        })
+        .collect::<StdResult<Vec<SalesHistoryBucket>>>()?;
+    Ok(SalesHistoryResponse {
+        bucket_duration: SALES_BUCKET_DURATION,
+        buckets,
+    })
+}
+
+pub fn query_pause(deps: Deps, env: Env) -> StdResult<QueryPauseResponse> {
+    let pause = PAUSE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .active_at(env.block.time);
+    Ok(QueryPauseResponse {
+        buy_paused: pause.buy_paused,
+        claim_paused: pause.claim_paused,
+        buy_expires_at: pause.buy_expires_at,
+        claim_expires_at: pause.claim_expires_at,
+    })
+}
+
+pub fn query_role_holders(
+    deps: Deps,
+    role: Role,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<QueryRoleHoldersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let holders = ROLES
+        .prefix(&role.to_string())
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|holder| holder.map(|holder| holder.to_string()))
+        .collect::<StdResult<Vec<String>>>()?;
+    Ok(QueryRoleHoldersResponse { role, holders })
+}
+
+pub fn query_purchase_history(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<PurchaseHistoryResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let purchases = USER_PURCHASES
+        .prefix(&addr)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|id| PURCHASES.load(deps.storage, id?))
+        .collect::<StdResult<Vec<Purchase>>>()?;
+    Ok(PurchaseHistoryResponse { purchases })
+}
+
+pub fn query_claim_history(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimHistoryResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let claims = USER_CLAIMS
+        .prefix(&addr)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|id| CLAIMS.load(deps.storage, id?))
+        .collect::<StdResult<Vec<ClaimReceipt>>>()?;
+    Ok(ClaimHistoryResponse { claims })
+}
+
+pub fn query_position_nft_metadata(deps: Deps, token_id: String) -> StdResult<Metadata> {
+    let addr = deps.api.addr_validate(&token_id)?;
+    let position = POSITIONS.load(deps.storage, addr)?;
+    Ok(position_metadata(&position))
+}
+
+pub fn query_position_value(
+    deps: Deps,
+    env: Env,
+    address: String,
+) -> StdResult<PositionValueResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let claimable = query_claimable(deps, env, address.clone(), None)?;
+    let position = POSITIONS.load(deps.storage, deps.api.addr_validate(&address)?)?;
+    let unclaimed = position.unclaimed();
+    Ok(PositionValueResponse {
+        address,
+        unclaimed,
+        claimable: claimable.amount,
+        next_unlock_time: claimable.next_unlock_time,
+        fully_vested_time: claimable.fully_vested_time,
+        price: config.price,
+        value: unclaimed * config.price,
+        buy_denom: config.buy_denom,
+        lien: position.lien,
+    })
+}
+
+pub fn query_lenders(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<LendersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let lenders = LENDERS
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|lender| lender.map(|lender| lender.to_string()))
+        .collect::<StdResult<Vec<String>>>()?;
+    Ok(LendersResponse { lenders })
+}
+
+pub fn query_revenue_vesting(deps: Deps, env: Env) -> StdResult<RevenueVestingResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let withdrawable = withdrawable_revenue(&config, &state, env.block.time);
+    Ok(RevenueVestingResponse {
+        schedule: config.revenue_vesting,
+        total: state.accumulated_revenue + state.revenue_withdrawn,
+        unlocked: state.revenue_withdrawn + withdrawable,
+        withdrawn: state.revenue_withdrawn,
+        withdrawable,
+    })
+}
+
+pub fn query_grants(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<GrantsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let grants = GRANTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, grant)| grant))
+        .collect::<StdResult<Vec<Grant>>>()?;
+    Ok(GrantsResponse { grants })
+}
+
+pub fn query_listings(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ListingsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let listings = LISTINGS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, listing)| listing))
+        .collect::<StdResult<Vec<Listing>>>()?;
+    Ok(ListingsResponse { listings })
+}
+
+pub fn query_listings_by_seller(
+    deps: Deps,
+    seller: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ListingsResponse> {
+    let seller = deps.api.addr_validate(&seller)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let listings = SELLER_LISTINGS
+        .prefix(&seller)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|id| LISTINGS.load(deps.storage, id?))
+        .collect::<StdResult<Vec<Listing>>>()?;
+    Ok(ListingsResponse { listings })
+}
+
+pub fn query_staking_contracts(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<StakingContractsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = maybe_addr(deps.api, start_after)?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let contracts = STAKING_CONTRACTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(address, hook_msg)| StakingContract {
+                address: address.to_string(),
+                hook_msg,
+            })
+        })
+        .collect::<StdResult<Vec<StakingContract>>>()?;
+    Ok(StakingContractsResponse { contracts })
+}
+
+pub fn query_allowlisted(deps: Deps, address: String) -> StdResult<QueryAllowlistedResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    Ok(QueryAllowlistedResponse {
+        allowlisted: ALLOWLIST.has(deps.storage, &addr),
+        address,
+    })
+}
+
+pub fn query_pending_config_changes(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<QueryPendingConfigChangesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let changes = PENDING_CONFIG_CHANGES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, change)| change))
+        .collect::<StdResult<Vec<PendingConfigChange>>>()?;
+    Ok(QueryPendingConfigChangesResponse { changes })
 }
@@ -0,0 +1,108 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_binary, Addr, CosmosMsg, QuerierWrapper, StdResult, WasmMsg};
+use serde::Deserialize;
+
+use crate::state::{Config, Position};
+
+// Subset of the cw721-base messages used to mint and look up position NFTs
+
+#[cw_serde]
+pub enum Cw721ExecuteMsg {
+    Mint {
+        token_id: String,
+        owner: String,
+        token_uri: Option<String>,
+        extension: Metadata,
+    },
+}
+
+#[cw_serde]
+pub enum Cw721QueryMsg {
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+}
+
+// Approvals in the response are not needed so unknown fields are allowed
+#[derive(Deserialize)]
+pub struct OwnerOfResponse {
+    pub owner: String,
+}
+
+// On-chain metadata in the cw721-metadata-onchain format
+#[cw_serde]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub attributes: Option<Vec<Trait>>,
+}
+
+#[cw_serde]
+pub struct Trait {
+    pub display_type: Option<String>,
+    pub trait_type: String,
+    pub value: String,
+}
+
+fn trait_value(trait_type: &str, value: String) -> Trait {
+    Trait {
+        display_type: None,
+        trait_type: trait_type.to_string(),
+        value,
+    }
+}
+
+// Describes the locked amount and unlock schedule of a position
+pub fn position_metadata(position: &Position) -> Metadata {
+    let mut attributes = vec![
+        trait_value("total_amount", position.total_bought.to_string()),
+        trait_value("locked_amount", position.unclaimed().to_string()),
+        trait_value("claimed_amount", position.total_claimed.to_string()),
+    ];
+    for batch in &position.batches {
+        attributes.push(trait_value(
+            &format!("unlock_{}", batch.release_time.nanos()),
+            batch.amount.to_string(),
+        ));
+    }
+    Metadata {
+        name: Some(format!("Vesting position {}", position.address)),
+        description: Some("Launchpad vesting position, claims follow the token owner".to_string()),
+        attributes: Some(attributes),
+    }
+}
+
+pub fn mint_msg(nft: &Addr, position: &Position) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: nft.to_string(),
+        msg: to_binary(&Cw721ExecuteMsg::Mint {
+            token_id: position.address.to_string(),
+            owner: position.address.to_string(),
+            token_uri: None,
+            extension: position_metadata(position),
+        })?,
+        funds: vec![],
+    }))
+}
+
+// Address that holds the claim rights of a position, the NFT owner once it is minted
+pub fn position_holder(
+    querier: QuerierWrapper,
+    config: &Config,
+    position: &Position,
+) -> StdResult<Addr> {
+    match (&config.position_nft, position.tokenized) {
+        (Some(nft), true) => {
+            let res: OwnerOfResponse = querier.query_wasm_smart(
+                nft,
+                &Cw721QueryMsg::OwnerOf {
+                    token_id: position.address.to_string(),
+                    include_expired: None,
+                },
+            )?;
+            Ok(Addr::unchecked(res.owner))
+        }
+        _ => Ok(position.address.clone()),
+    }
+}
@@ -1,4 +1,6 @@
-use cosmwasm_std::{ConversionOverflowError, DivideByZeroError, OverflowError, StdError};
+use cosmwasm_std::{
+    ConversionOverflowError, DivideByZeroError, OverflowError, StdError, Timestamp, Uint128,
+};
 use cw_asset::AssetError;
 use cw_utils::PaymentError;
 use std::convert::Infallible;
@@ -46,6 +48,132 @@ pub enum ContractError {
 
     #[error("Price can not be zero or negative")]
     InvalidPrice {},
+
+    #[error("Purchase is already cancelled")]
+    PurchaseCancelled {},
+
+    #[error("Cooling-off period of the purchase has passed")]
+    CoolingOffPeriodPassed {},
+
+    #[error("There is no revenue to forward")]
+    NoRevenueToForward {},
+
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Pause duration can not exceed {max} seconds")]
+    InvalidPauseDuration { max: u64 },
+
+    #[error("There is no pending admin proposal")]
+    NoAdminProposal {},
+
+    #[error("Admin proposal has expired")]
+    AdminProposalExpired {},
+
+    #[error("Address is not allowlisted")]
+    NotAllowlisted {},
+
+    #[error("Config change can not be executed before {executable_at}")]
+    TimelockNotExpired { executable_at: Timestamp },
+
+    #[error("Cannot migrate from {previous_contract} {previous_version}")]
+    CannotMigrate {
+        previous_contract: String,
+        previous_version: String,
+    },
+
+    #[error("Funds are not enough to buy a token")]
+    ZeroBuyAmount {},
+
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
+    #[error("Position owner has not allowed claiming on their behalf")]
+    ClaimForNotAllowed {},
+
+    #[error("Staking contract is not allowed")]
+    StakingContractNotAllowed {},
+
+    #[error("Position transfers are disabled")]
+    TransfersDisabled {},
+
+    #[error("Transfer amount must be positive and at most the unclaimed amount")]
+    InvalidTransferAmount {},
+
+    #[error("Can not transfer a position to its owner")]
+    CannotTransferToSelf {},
+
+    #[error("Position is minted as an NFT, use its token id")]
+    PositionTokenized {},
+
+    #[error("Position NFTs are not enabled")]
+    PositionNftNotEnabled {},
+
+    #[error("OTC fee rate must be lower than 1")]
+    InvalidOtcFee {},
+
+    #[error("Can not fill your own listing")]
+    CannotFillOwnListing {},
+
+    #[error("Listing costs {required}")]
+    InsufficientFunds { required: Uint128 },
+
+    #[error("Lender is not approved")]
+    LenderNotApproved {},
+
+    #[error("Position is under lien")]
+    LienActive {},
+
+    #[error("Position has no lien")]
+    NoLien {},
+
+    #[error("Address already has a position")]
+    PositionExists {},
+
+    #[error("Position is an admin grant and can not take purchases or transfers")]
+    PositionGranted {},
+
+    #[error("Grant amount must be positive and match its schedule")]
+    InvalidGrant {},
+
+    #[error("Only revocable grants can be revoked")]
+    NotRevocable {},
+
+    #[error("Grant is already revoked")]
+    GrantRevoked {},
+
+    #[error("Buyers can not refer themselves")]
+    SelfReferral {},
+
+    #[error("Referral rate must be below 1")]
+    InvalidReferralRate {},
+
+    #[error("Revenue recipients must have positive weights that add up to one")]
+    InvalidRevenueRecipients {},
+
+    #[error("Platform fee must be below 1 and have a collector")]
+    InvalidPlatformFee {},
+
+    #[error("No revenue to withdraw")]
+    NoRevenueToWithdraw {},
+
+    #[error("Invalid revenue vesting schedule")]
+    InvalidRevenueVesting {},
+
+    #[error("Guardian can not pause again before the cooldown has passed")]
+    PauseCooldown {},
+
+    #[error("Position has open listings")]
+    OpenListings {},
+
+    #[error("Tokens can not be added to released batches")]
+    BatchReleased {},
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        ContractError::SemVer(err.to_string())
+    }
 }
 
 impl From<AssetError> for ContractError {
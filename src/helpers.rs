@@ -1 +1,226 @@
+use std::collections::BTreeMap;
 
+use cosmwasm_std::{Addr, Api, Decimal, Env, StdResult, Storage, Timestamp, Uint128};
+
+use crate::{
+    state::{
+        Batch, Config, ConfigChange, Position, RevenueRecipient, RevenueVesting, Role, State,
+        Status, GRANTS, PAUSE, ROLES, UNLOCKS,
+    },
+    ContractError,
+};
+
+pub fn assert_admin(config: &Config, sender: &Addr) -> Result<(), ContractError> {
+    // Nobody is admin once admin rights are renounced
+    if config.admin.as_ref() != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+pub fn has_role(
+    storage: &dyn Storage,
+    config: &Config,
+    sender: &Addr,
+    role: Role,
+) -> Result<bool, ContractError> {
+    if config.admin.as_ref() == Some(sender) {
+        return Ok(true);
+    }
+    Ok(ROLES.has(storage, (&role.to_string(), sender)))
+}
+
+pub fn assert_role(
+    storage: &dyn Storage,
+    config: &Config,
+    sender: &Addr,
+    role: Role,
+) -> Result<(), ContractError> {
+    if !has_role(storage, config, sender, role)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+pub fn assert_buy_not_paused(storage: &dyn Storage, env: &Env) -> Result<(), ContractError> {
+    let pause = PAUSE.may_load(storage)?.unwrap_or_default();
+    if pause.active_at(env.block.time).buy_paused {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
+
+pub fn assert_claim_not_paused(storage: &dyn Storage, env: &Env) -> Result<(), ContractError> {
+    let pause = PAUSE.may_load(storage)?.unwrap_or_default();
+    if pause.active_at(env.block.time).claim_paused {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
+
+// Positions can change hands once purchases are final, unless the admin disabled it
+pub fn assert_transferable(config: &Config, state: &State) -> Result<(), ContractError> {
+    if !config.transfers_enabled {
+        return Err(ContractError::TransfersDisabled {});
+    }
+    if state.status != Status::Distribution {
+        return Err(ContractError::SaleNotDistribution {});
+    }
+    Ok(())
+}
+
+// Positions minted as NFTs can only be used through their token id
+pub fn assert_not_tokenized(position: &Position) -> Result<(), ContractError> {
+    if position.tokenized {
+        return Err(ContractError::PositionTokenized {});
+    }
+    Ok(())
+}
+
+// Positions under lien stay with the owner until the lender releases or seizes them
+pub fn assert_no_lien(position: &Position) -> Result<(), ContractError> {
+    if position.lien.is_some() {
+        return Err(ContractError::LienActive {});
+    }
+    Ok(())
+}
+
+// Granted positions keep their own schedule and are not mixed with purchased tokens
+pub fn assert_not_granted(storage: &dyn Storage, address: &Addr) -> Result<(), ContractError> {
+    if GRANTS.has(storage, address) {
+        return Err(ContractError::PositionGranted {});
+    }
+    Ok(())
+}
+
+// Sold, granted and referral tokens that are not claimed yet stay in the contract
+pub fn tokens_owed(state: &State) -> Uint128 {
+    state.total_sold + state.total_granted + state.total_referral_tokens - state.total_claimed
+}
+
+pub fn assert_can_change_config(
+    storage: &dyn Storage,
+    config: &Config,
+    sender: &Addr,
+    change: &ConfigChange,
+) -> Result<(), ContractError> {
+    match change {
+        ConfigChange::Price { .. } => assert_admin(config, sender),
+        ConfigChange::RevenueRecipients { .. } => {
+            assert_role(storage, config, sender, Role::Treasurer)
+        }
+    }
+}
+
+pub fn apply_config_change(
+    api: &dyn Api,
+    config: &mut Config,
+    change: ConfigChange,
+) -> Result<(), ContractError> {
+    match change {
+        ConfigChange::Price { price } => {
+            // check price
+            if price.is_zero() {
+                return Err(ContractError::InvalidPrice {});
+            }
+            config.price = price;
+        }
+        ConfigChange::RevenueRecipients { recipients } => {
+            config.revenue_recipients = validate_revenue_recipients(api, recipients)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_revenue_recipients(
+    api: &dyn Api,
+    recipients: Vec<(String, Decimal)>,
+) -> Result<Vec<RevenueRecipient>, ContractError> {
+    let total: Decimal = recipients.iter().map(|(_, weight)| *weight).sum();
+    if total != Decimal::one() || recipients.iter().any(|(_, weight)| weight.is_zero()) {
+        return Err(ContractError::InvalidRevenueRecipients {});
+    }
+    recipients
+        .into_iter()
+        .map(|(address, weight)| {
+            Ok(RevenueRecipient {
+                address: api.addr_validate(&address)?,
+                weight,
+            })
+        })
+        .collect()
+}
+
+pub fn validate_revenue_vesting(vesting: RevenueVesting) -> Result<RevenueVesting, ContractError> {
+    match vesting {
+        RevenueVesting::Linear {
+            cliff, duration, ..
+        } if duration == 0 || cliff > duration => Err(ContractError::InvalidRevenueVesting {}),
+        RevenueVesting::Milestones { mut milestones } => {
+            let total: Decimal = milestones.iter().map(|(_, share)| *share).sum();
+            if total != Decimal::one() || milestones.iter().any(|(_, share)| share.is_zero()) {
+                return Err(ContractError::InvalidRevenueVesting {});
+            }
+            milestones.sort_by_key(|(unlock_time, _)| *unlock_time);
+            Ok(RevenueVesting::Milestones { milestones })
+        }
+        vesting => Ok(vesting),
+    }
+}
+
+// Accumulated revenue that can be withdrawn at the given time
+pub fn withdrawable_revenue(config: &Config, state: &State, time: Timestamp) -> Uint128 {
+    match &config.revenue_vesting {
+        Some(vesting) => {
+            let total = state.accumulated_revenue + state.revenue_withdrawn;
+            (total * vesting.unlocked_ratio(time))
+                .saturating_sub(state.revenue_withdrawn)
+                .min(state.accumulated_revenue)
+        }
+        None => state.accumulated_revenue,
+    }
+}
+
+// Splits revenue into the platform fee and the recipient shares, rounding dust goes to the
+// first recipient
+pub fn revenue_payouts(config: &Config, amount: Uint128) -> Vec<(Addr, Uint128)> {
+    let mut payouts = vec![];
+    let mut rest = amount;
+    if let Some(fee) = &config.platform_fee {
+        let fee_amount = amount * fee.rate;
+        rest -= fee_amount;
+        payouts.push((fee.collector.clone(), fee_amount));
+    }
+    let shares: Vec<Uint128> = config
+        .revenue_recipients
+        .iter()
+        .map(|recipient| rest * recipient.weight)
+        .collect();
+    let dust = rest - shares.iter().sum::<Uint128>();
+    for (i, (recipient, share)) in config.revenue_recipients.iter().zip(shares).enumerate() {
+        let share = if i == 0 { share + dust } else { share };
+        payouts.push((recipient.address.clone(), share));
+    }
+    payouts.retain(|(_, amount)| !amount.is_zero());
+    payouts
+}
+
+// Applies a change of a position's batches to the contract-wide unlock schedule
+pub fn update_unlocks(storage: &mut dyn Storage, old: &[Batch], new: &[Batch]) -> StdResult<()> {
+    let mut changes: BTreeMap<u64, (Uint128, Uint128)> = BTreeMap::new();
+    for batch in old {
+        changes.entry(batch.release_time.nanos()).or_default().1 += batch.amount;
+    }
+    for batch in new {
+        changes.entry(batch.release_time.nanos()).or_default().0 += batch.amount;
+    }
+    for (release_time, (added, removed)) in changes {
+        if added == removed {
+            continue;
+        }
+        UNLOCKS.update(storage, release_time, |amount| -> StdResult<_> {
+            Ok(amount.unwrap_or_default() + added - removed)
+        })?;
+    }
+    Ok(())
+}
@@ -1,7 +1,9 @@
 pub mod batch;
 pub mod contract;
+pub mod cw721;
 mod error;
 pub mod helpers;
+pub mod migrations;
 pub mod msg;
 pub mod state;
 
@@ -0,0 +1,170 @@
+use cosmwasm_std::{Decimal, Order, StdResult, Storage, Uint128};
+
+use crate::batch::create_batches;
+use crate::helpers::update_unlocks;
+use crate::state::{
+    Batch, Bathces, Config, PauseState, Position, RevenueRecipient, State, CONFIG,
+    DEFAULT_CONFIG_TIMELOCK, PAUSE, POSITIONS, STATE,
+};
+use crate::ContractError;
+
+// Layouts of v0.1.0, which did not set a cw2 contract version
+pub mod v0_1 {
+    use cosmwasm_schema::cw_serde;
+    use cosmwasm_std::{Addr, Decimal, Timestamp, Uint128};
+    use cw_storage_plus::{Item, Map};
+
+    use crate::state::{Bathces, Status};
+
+    pub const VERSION: &str = "0.1.0";
+
+    #[cw_serde]
+    pub struct Config {
+        pub admin: Addr,
+        pub batch_duration: Uint128,
+        pub batch_amount: Uint128,
+        pub revenue_collector: Addr,
+        pub price: Decimal,
+        pub buy_denom: String,
+        pub sell_denom: Addr,
+        pub first_batch_release_time: Timestamp,
+    }
+    pub const CONFIG: Item<Config> = Item::new("config");
+
+    #[cw_serde]
+    pub struct State {
+        pub total_revenue: Uint128,
+        pub total_sold: Uint128,
+        pub status: Status,
+    }
+    pub const STATE: Item<State> = Item::new("state");
+
+    #[cw_serde]
+    pub struct Position {
+        pub address: Addr,
+        pub total_bought: Uint128,
+        pub total_paid: Uint128,
+        pub total_claimed: Uint128,
+        pub price: Decimal,
+        pub timestamp: Timestamp,
+        pub batches: Bathces,
+    }
+    pub const POSITIONS: Map<Addr, Position> = Map::new("positions");
+}
+
+pub fn migrate_from_v0_1(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    let old_config = v0_1::CONFIG.load(storage)?;
+    let config = Config {
+        admin: Some(old_config.admin),
+        batch_duration: old_config.batch_duration,
+        batch_amount: old_config.batch_amount,
+        revenue_recipients: vec![RevenueRecipient {
+            address: old_config.revenue_collector,
+            weight: Decimal::one(),
+        }],
+        platform_fee: None,
+        accumulate_revenue: false,
+        revenue_vesting: None,
+        price: old_config.price,
+        buy_denom: old_config.buy_denom,
+        sell_denom: old_config.sell_denom,
+        first_batch_release_time: old_config.first_batch_release_time,
+        // v0.1 forwarded revenue instantly and had none of the later features
+        cooling_off_period: 0,
+        guardian: None,
+        max_pause_duration: None,
+        allowlist_enabled: false,
+        config_timelock: DEFAULT_CONFIG_TIMELOCK,
+        transfers_enabled: true,
+        position_nft: None,
+        otc_fee: None,
+        referral: None,
+    };
+    CONFIG.save(storage, &config)?;
+
+    // All positions are converted in this one transaction, sales with more positions than
+    // fit in the block gas limit have to be migrated with a raised limit
+    let old_positions = v0_1::POSITIONS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let old_state = v0_1::STATE.load(storage)?;
+    let state = State {
+        total_revenue: old_state.total_revenue,
+        total_sold: old_state.total_sold,
+        status: old_state.status,
+        pending_revenue: Uint128::zero(),
+        total_granted: Uint128::zero(),
+        total_referral_tokens: Uint128::zero(),
+        accumulated_revenue: Uint128::zero(),
+        revenue_withdrawn: Uint128::zero(),
+        // v0.1 did not record purchases, these are rebuilt from positions where possible
+        unique_buyers: old_positions.len() as u64,
+        purchase_count: 0,
+        largest_purchase: Uint128::zero(),
+        total_claimed: old_positions
+            .iter()
+            .map(|(_, position)| position.total_claimed)
+            .sum(),
+    };
+    STATE.save(storage, &state)?;
+    PAUSE.save(storage, &PauseState::default())?;
+
+    for (address, old_position) in old_positions {
+        let batches = restore_released_batches(&config, &old_position)?;
+        let mut position = Position {
+            address: old_position.address,
+            total_bought: old_position.total_bought,
+            total_paid: old_position.total_paid,
+            total_claimed: old_position.total_claimed,
+            price: old_position.price,
+            timestamp: old_position.timestamp,
+            average_price: Decimal::zero(),
+            allow_claim_for: false,
+            tokenized: false,
+            lien: None,
+            batches,
+        };
+        position.update_average_price();
+        POSITIONS.save(storage, address, &position)?;
+        update_unlocks(storage, &[], &position.batches)?;
+    }
+    Ok(())
+}
+
+// v0.1 deleted batches once claimed, add them back as released so the full schedule is kept.
+// Every batch of a position holds the same amount so the claimed total splits evenly.
+fn restore_released_batches(
+    config: &Config,
+    old_position: &v0_1::Position,
+) -> Result<Bathces, ContractError> {
+    let schedule = create_batches(
+        config.batch_duration,
+        config.batch_amount,
+        Uint128::zero(),
+        config.first_batch_release_time,
+    )?;
+    let claimed_count = schedule.len().saturating_sub(old_position.batches.len());
+    if claimed_count == 0 {
+        return Ok(old_position.batches.clone());
+    }
+    let claimed_amount = old_position
+        .total_claimed
+        .checked_div(Uint128::from(claimed_count as u128))?;
+    let batches = schedule
+        .into_iter()
+        .map(|batch| {
+            old_position
+                .batches
+                .iter()
+                .find(|old_batch| old_batch.release_time == batch.release_time)
+                .cloned()
+                .unwrap_or(Batch {
+                    amount: claimed_amount,
+                    release_time: batch.release_time,
+                    released: true,
+                })
+        })
+        .collect();
+    Ok(batches)
+}
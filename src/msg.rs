@@ -1,7 +1,11 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Decimal, Timestamp, Uint128};
+use cosmwasm_std::{Coin, Decimal, Timestamp, Uint128};
 
-use crate::state::Batch;
+use crate::state::{
+    Batch, ClaimReceipt, ConfigChange, Grant, Lien, Listing, OtcFee, PendingConfigChange,
+    PlatformFee, Position, Purchase, ReferralConfig, ReferralReward, RevenueRecipient,
+    RevenueVesting, Role,
+};
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -11,7 +15,15 @@ pub struct InstantiateMsg {
     // Batch amount eg. 10 batches, duration= x nanoseconds total duration = 10x
     pub batch_amount: Uint128,
 
-    pub revenue_collector: String,
+    // Addresses and weights revenue is split between, the weights must add up to one
+    pub revenue_recipients: Vec<(String, Decimal)>,
+    // Cut of the revenue taken by the platform, only platform_fee_collector can change it
+    pub platform_fee: Option<Decimal>,
+    pub platform_fee_collector: Option<String>,
+    // Hold revenue for WithdrawRevenue instead of paying it out with each buy
+    pub accumulate_revenue: Option<bool>,
+    // Held revenue only unlocks on this schedule, it can not be changed later
+    pub revenue_vesting: Option<RevenueVesting>,
     // Price of cw20 token in native token
     pub price: Decimal,
     // In this case buy_denom is native token
@@ -20,19 +32,45 @@ pub struct InstantiateMsg {
     pub sell_denom: String,
     // First batch release time. This shouldnt be changed once the sale is started.
     pub first_batch_release_time: Timestamp,
+    // Seconds after a purchase during which the buyer can cancel it. Revenue is held until it passes.
+    pub cooling_off_period: Option<u64>,
+    // Address that can pause buys and claims
+    pub guardian: Option<String>,
+    // Longest pause the guardian can set in seconds
+    pub max_pause_duration: Option<u64>,
+    // Only allowlisted addresses can buy if enabled
+    pub allowlist_enabled: Option<bool>,
+    // Seconds a proposed price or revenue recipients change waits before it can be executed,
+    // defaults to one day
+    pub config_timelock: Option<u64>,
+    // Positions can be transferred if enabled, defaults to true
+    pub transfers_enabled: Option<bool>,
+    // CW721 contract with this contract as minter, enables minting positions as NFTs
+    pub position_nft: Option<String>,
 }
 
 #[cw_serde]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
-    Buy {},
+    // The referrer is recorded with the buyer's first purchase
+    Buy {
+        referrer: Option<String>,
+    },
     UpdateConfig {
-        admin: Option<String>,
         batch_duration: Option<Uint128>,
         batch_amount: Option<Uint128>,
-        revenue_collector: Option<String>,
+        revenue_recipients: Option<Vec<(String, Decimal)>>,
+        accumulate_revenue: Option<bool>,
         price: Option<Decimal>,
         buy_denom: Option<String>,
         sell_denom: Option<String>,
+        cooling_off_period: Option<u64>,
+        guardian: Option<String>,
+        max_pause_duration: Option<u64>,
+        allowlist_enabled: Option<bool>,
+        config_timelock: Option<u64>,
+        transfers_enabled: Option<bool>,
+        position_nft: Option<String>,
     },
     StartDistribution {},
     // Withdraw remaning cw20 tokens. Checks balance and sends remaining tokens to admin
@@ -40,14 +78,270 @@ pub enum ExecuteMsg {
         amount: Uint128,
     },
     StartSale {},
-    Claim {},
+    // Sends matured tokens to recipient, the sender by default. token_id claims a position minted
+    // as an NFT and is only accepted from the NFT owner
+    Claim {
+        recipient: Option<String>,
+        token_id: Option<String>,
+    },
+    // Mints the sender's position as an NFT once distribution has started
+    MintPositionNft {},
+    // Claims for an owner that allowed it, tokens always go to the owner
+    ClaimFor {
+        owner: String,
+    },
+    // Allows or forbids anyone to call ClaimFor on the sender's position
+    SetAllowClaimFor {
+        allowed: bool,
+    },
+    // Claims matured tokens into an allowed staking contract through cw20 Send
+    ClaimAndStake {
+        staking_contract: String,
+    },
+    // Allows a staking contract with the hook message template to send, {owner} is replaced by the
+    // claiming address. None removes the contract
+    UpdateStakingContract {
+        address: String,
+        hook_msg: Option<String>,
+    },
+    // Moves amount of unclaimed tokens, all if None, to the recipient's position
+    TransferPosition {
+        recipient: String,
+        amount: Option<Uint128>,
+    },
+    // Moves each amount of unclaimed tokens to its recipient, splitting the schedule proportionally
+    SplitPosition {
+        shares: Vec<(String, Uint128)>,
+    },
+    // Moves the whole position, claimed batches included, into the recipient's position
+    MergeInto {
+        recipient: String,
+    },
+    // Escrows amount of the sender's unclaimed tokens for sale at price per token in buy_denom
+    CreateListing {
+        amount: Uint128,
+        price: Decimal,
+    },
+    // Returns the escrowed tokens to the seller
+    CancelListing {
+        id: u64,
+    },
+    // Buys the whole listing, the payment goes to the seller minus the OTC fee
+    FillListing {
+        id: u64,
+    },
+    // Sets the cut of OTC fills, zero rate disables the fee
+    UpdateOtcFee {
+        rate: Decimal,
+        collector: String,
+    },
+    // Pays the unlocked part of the accumulated revenue to the platform and the revenue recipients
+    WithdrawRevenue {},
+    // Platform fee collector changes its rate or address, zero rate removes the fee
+    UpdatePlatformFee {
+        rate: Decimal,
+        collector: String,
+    },
+    // Sets the referral reward before the sale starts, zero rate disables it
+    UpdateReferralConfig {
+        rate: Decimal,
+        reward: ReferralReward,
+    },
+    // Admin records a vesting allocation without payment, on the sale batches if schedule is None
+    GrantAllocation {
+        address: String,
+        amount: Uint128,
+        schedule: Option<Vec<UnlockBucket>>,
+        revocable: bool,
+    },
+    // Pays the vested tokens of a revocable grant to its holder and returns the rest to the sender
+    RevokeAllocation {
+        address: String,
+    },
+    // Admin approves and removes lender contracts
+    UpdateLenders {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    // Pledges the sender's position to an approved lender. Claims go to the lender if
+    // redirect_claims is set, otherwise they are blocked until the lien is released
+    GrantLien {
+        lender: String,
+        redirect_claims: bool,
+    },
+    // Lender releases the lien on a position
+    ReleaseLien {
+        owner: String,
+    },
+    // Lender takes over the unclaimed tokens of a position under its lien
+    SeizePosition {
+        owner: String,
+    },
+    // Sends matured tokens to a page of position owners, returns DistributeMaturedResponse as data
+    DistributeMatured {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Refunds a purchase made within the cooling-off period
+    CancelPurchase {
+        purchase_id: u64,
+    },
+    // Pays out revenue of purchases whose cooling-off period has passed
+    ForwardRevenue {
+        limit: Option<u32>,
+    },
+    // Guardian or admin pauses buys and/or claims, optionally until duration seconds have passed
+    Pause {
+        buy: bool,
+        claim: bool,
+        duration: Option<u64>,
+    },
+    Unpause {
+        buy: bool,
+        claim: bool,
+    },
+    // Admin proposes a new admin who has expires_in seconds to accept
+    ProposeAdmin {
+        address: String,
+        expires_in: u64,
+    },
+    AcceptAdmin {},
+    CancelAdminProposal {},
+    // Removes the admin for good, making the sale immutable
+    RenounceAdmin {},
+    GrantRole {
+        role: Role,
+        address: String,
+    },
+    RevokeRole {
+        role: Role,
+        address: String,
+    },
+    // Allowlist manager adds and removes addresses from the allowlist
+    UpdateAllowlist {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    // Queues a price or revenue recipients change once the sale has started
+    ProposeConfigChange {
+        change: ConfigChange,
+    },
+    // Applies a queued change after config_timelock has passed
+    ExecuteConfigChange {
+        id: u64,
+    },
+    CancelConfigChange {
+        id: u64,
+    },
 }
 
+#[cw_serde]
+pub struct MigrateMsg {}
+
 #[cw_serde]
 pub enum QueryMsg {
-    QueryPosition { address: String },
+    QueryPosition {
+        address: String,
+    },
     QueryConfig {},
     QueryState {},
+    QueryPurchase {
+        purchase_id: u64,
+    },
+    // Purchase receipts of a buyer, including cancelled ones
+    PurchaseHistory {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Live metadata of a position NFT, returns cw721::Metadata
+    PositionNftMetadata {
+        token_id: String,
+    },
+    // Valuation of a position for lenders
+    PositionValue {
+        address: String,
+    },
+    Lenders {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    Grants {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    ReferralStats {
+        address: String,
+    },
+    // Held revenue and how much of it is unlocked now
+    RevenueVesting {},
+    Listing {
+        id: u64,
+    },
+    Listings {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    ListingsBySeller {
+        seller: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    StakingContracts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Claim receipts of a position owner
+    ClaimHistory {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    QueryPause {},
+    QueryRoleHolders {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    QueryAllowlisted {
+        address: String,
+    },
+    QueryPendingConfigChanges {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Lists holders with position totals
+    AllPositions {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Lists raw positions, enough to rebuild them elsewhere
+    ExportPositions {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Amount a claim would pay at at_time, defaults to now
+    Claimable {
+        address: String,
+        at_time: Option<Timestamp>,
+    },
+    // Tokens unlocking at each release time between start and end, over all positions
+    UnlockSchedule {
+        start: Option<Timestamp>,
+        end: Option<Timestamp>,
+    },
+    // Quote of what Buy would do with the given funds, runs the same checks as Buy
+    SimulateBuy {
+        buyer: String,
+        funds: Vec<Coin>,
+        referrer: Option<String>,
+    },
+    SaleStats {},
+    // Revenue and tokens sold per hour, start_after is a bucket start in seconds
+    SalesHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 #[cw_serde]
 pub struct QueryPositionResponse {
@@ -57,22 +351,196 @@ pub struct QueryPositionResponse {
     pub total_claimed: Uint128,
     pub price: Decimal,
     pub timestamp: Timestamp,
+    pub average_price: Decimal,
+    pub allow_claim_for: bool,
+    pub tokenized: bool,
+    pub lien: Option<Lien>,
     pub batches: Vec<Batch>,
 }
 #[cw_serde]
 pub struct QueryConfigResponse {
-    pub admin: String,
+    pub admin: Option<String>,
+    pub pending_admin: Option<String>,
+    pub pending_admin_expires_at: Option<Timestamp>,
     pub batch_duration: Uint128,
     pub batch_amount: Uint128,
-    pub revenue_collector: String,
+    pub revenue_recipients: Vec<RevenueRecipient>,
+    pub platform_fee: Option<PlatformFee>,
+    pub accumulate_revenue: bool,
+    pub revenue_vesting: Option<RevenueVesting>,
     pub price: Decimal,
     pub buy_denom: String,
     pub sell_denom: String,
     pub first_batch_release_time: Timestamp,
+    pub cooling_off_period: u64,
+    pub guardian: Option<String>,
+    pub max_pause_duration: Option<u64>,
+    pub allowlist_enabled: bool,
+    pub config_timelock: u64,
+    pub transfers_enabled: bool,
+    pub position_nft: Option<String>,
+    pub otc_fee: Option<OtcFee>,
+    pub referral: Option<ReferralConfig>,
 }
 #[cw_serde]
 pub struct QueryStateResponse {
     pub total_revenue: Uint128,
     pub total_sold: Uint128,
     pub status: String,
+    pub pending_revenue: Uint128,
+    pub total_granted: Uint128,
+    pub total_referral_tokens: Uint128,
+    pub accumulated_revenue: Uint128,
+}
+#[cw_serde]
+pub struct QueryPauseResponse {
+    pub buy_paused: bool,
+    pub claim_paused: bool,
+    pub buy_expires_at: Option<Timestamp>,
+    pub claim_expires_at: Option<Timestamp>,
+}
+#[cw_serde]
+pub struct QueryRoleHoldersResponse {
+    pub role: Role,
+    pub holders: Vec<String>,
+}
+#[cw_serde]
+pub struct QueryAllowlistedResponse {
+    pub address: String,
+    pub allowlisted: bool,
+}
+#[cw_serde]
+pub struct QueryPendingConfigChangesResponse {
+    pub changes: Vec<PendingConfigChange>,
+}
+#[cw_serde]
+pub struct PositionSummary {
+    pub address: String,
+    pub total_bought: Uint128,
+    pub total_paid: Uint128,
+    pub total_claimed: Uint128,
+    pub unclaimed: Uint128,
+    pub price: Decimal,
+    pub average_price: Decimal,
+}
+#[cw_serde]
+pub struct AllPositionsResponse {
+    pub positions: Vec<PositionSummary>,
+}
+#[cw_serde]
+pub struct ExportPositionsResponse {
+    pub positions: Vec<Position>,
+}
+#[cw_serde]
+pub struct ClaimableResponse {
+    pub address: String,
+    pub at_time: Timestamp,
+    pub amount: Uint128,
+    pub next_unlock_time: Option<Timestamp>,
+    pub next_unlock_amount: Uint128,
+    pub fully_vested_time: Option<Timestamp>,
+}
+#[cw_serde]
+pub struct UnlockBucket {
+    pub release_time: Timestamp,
+    pub amount: Uint128,
+}
+#[cw_serde]
+pub struct UnlockScheduleResponse {
+    pub unlocks: Vec<UnlockBucket>,
+    pub total: Uint128,
+}
+#[cw_serde]
+pub struct SimulateBuyResponse {
+    pub buy_amount: Uint128,
+    pub amount_paid: Uint128,
+    pub effective_price: Option<Decimal>,
+    // Part of the funds that does not buy a whole token
+    pub refund: Uint128,
+    // Batches of the buyer's position after the buy
+    pub batches: Vec<Batch>,
+    // Error Buy would fail with
+    pub error: Option<String>,
+}
+#[cw_serde]
+pub struct SaleStatsResponse {
+    pub total_revenue: Uint128,
+    pub total_sold: Uint128,
+    pub unique_buyers: u64,
+    pub purchase_count: u64,
+    pub largest_purchase: Uint128,
+    pub total_claimed: Uint128,
+}
+#[cw_serde]
+pub struct SalesHistoryBucket {
+    // Bucket start in seconds
+    pub start: u64,
+    pub revenue: Uint128,
+    pub tokens_sold: Uint128,
+    pub purchases: u64,
+}
+#[cw_serde]
+pub struct SalesHistoryResponse {
+    pub bucket_duration: u64,
+    pub buckets: Vec<SalesHistoryBucket>,
+}
+#[cw_serde]
+pub struct PurchaseHistoryResponse {
+    pub purchases: Vec<Purchase>,
+}
+#[cw_serde]
+pub struct DistributeMaturedResponse {
+    pub distributed: Uint128,
+    pub recipients: u32,
+    // Pass as start_after to distribute the next page, None once all positions are walked
+    pub next_start_after: Option<String>,
+}
+#[cw_serde]
+pub struct PositionValueResponse {
+    pub address: String,
+    // Tokens that are not claimed yet, vested or not
+    pub unclaimed: Uint128,
+    // Part of unclaimed that can be claimed now
+    pub claimable: Uint128,
+    pub next_unlock_time: Option<Timestamp>,
+    pub fully_vested_time: Option<Timestamp>,
+    // Sale price and the unclaimed amount valued at it, in buy_denom
+    pub price: Decimal,
+    pub value: Uint128,
+    pub buy_denom: String,
+    pub lien: Option<Lien>,
+}
+#[cw_serde]
+pub struct LendersResponse {
+    pub lenders: Vec<String>,
+}
+#[cw_serde]
+pub struct RevenueVestingResponse {
+    pub schedule: Option<RevenueVesting>,
+    // Revenue held so far, withdrawn or not
+    pub total: Uint128,
+    pub unlocked: Uint128,
+    pub withdrawn: Uint128,
+    pub withdrawable: Uint128,
+}
+#[cw_serde]
+pub struct GrantsResponse {
+    pub grants: Vec<Grant>,
+}
+#[cw_serde]
+pub struct ListingsResponse {
+    pub listings: Vec<Listing>,
+}
+#[cw_serde]
+pub struct StakingContract {
+    pub address: String,
+    pub hook_msg: String,
+}
+#[cw_serde]
+pub struct StakingContractsResponse {
+    pub contracts: Vec<StakingContract>,
+}
+#[cw_serde]
+pub struct ClaimHistoryResponse {
+    pub claims: Vec<ClaimReceipt>,
 }
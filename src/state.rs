@@ -1,20 +1,175 @@
+use std::fmt;
+
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Decimal, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Decimal, Empty, Timestamp, Uint128};
 use cw_storage_plus::{Item, Map};
 
 #[cw_serde]
 pub struct Config {
-    pub admin: Addr,
+    // None once admin rights are renounced
+    pub admin: Option<Addr>,
     pub batch_duration: Uint128,
     pub batch_amount: Uint128,
-    pub revenue_collector: Addr,
+    // Revenue is split between these by weight, the weights add up to one
+    pub revenue_recipients: Vec<RevenueRecipient>,
+    // Cut of the revenue taken by the platform before the split
+    pub platform_fee: Option<PlatformFee>,
+    // Revenue is held for WithdrawRevenue instead of paid out with each buy or forward
+    pub accumulate_revenue: bool,
+    // Held revenue unlocks on this schedule, implies accumulate_revenue
+    pub revenue_vesting: Option<RevenueVesting>,
     pub price: Decimal,
     pub buy_denom: String,
     pub sell_denom: Addr,
     pub first_batch_release_time: Timestamp,
+    // Seconds after a purchase during which the buyer can cancel it
+    pub cooling_off_period: u64,
+    // Can pause buys and claims without being admin
+    pub guardian: Option<Addr>,
+    // Longest pause the guardian can set in seconds, pauses without expiry are not allowed if set
+    pub max_pause_duration: Option<u64>,
+    // Only allowlisted addresses can buy if enabled
+    pub allowlist_enabled: bool,
+    // Seconds a proposed price or revenue recipients change waits before it can be executed
+    pub config_timelock: u64,
+    // Holders can transfer their positions if set
+    pub transfers_enabled: bool,
+    // CW721 contract minted by this contract, positions can be minted as NFTs if set
+    pub position_nft: Option<Addr>,
+    // Cut of OTC fills sent to the collector
+    pub otc_fee: Option<OtcFee>,
+    // Rewards credited to referrers on each referred purchase
+    pub referral: Option<ReferralConfig>,
 }
 pub const CONFIG: Item<Config> = Item::new("config");
 
+// Timelock of config changes when none is given, one day
+pub const DEFAULT_CONFIG_TIMELOCK: u64 = 86400;
+
+#[cw_serde]
+pub struct RevenueRecipient {
+    pub address: Addr,
+    pub weight: Decimal,
+}
+
+#[cw_serde]
+pub enum RevenueVesting {
+    // Nothing unlocks before start + cliff seconds, then linearly until start + duration seconds
+    Linear {
+        start: Timestamp,
+        cliff: u64,
+        duration: u64,
+    },
+    // Share of the revenue unlocked at each time, the shares add up to one
+    Milestones {
+        milestones: Vec<(Timestamp, Decimal)>,
+    },
+}
+
+impl RevenueVesting {
+    // Share of all held revenue that is unlocked at the given time
+    pub fn unlocked_ratio(&self, time: Timestamp) -> Decimal {
+        match self {
+            RevenueVesting::Linear {
+                start,
+                cliff,
+                duration,
+            } => {
+                if time < start.plus_seconds(*cliff) {
+                    return Decimal::zero();
+                }
+                let elapsed = time.seconds() - start.seconds();
+                if elapsed >= *duration {
+                    Decimal::one()
+                } else {
+                    Decimal::from_ratio(elapsed, *duration)
+                }
+            }
+            RevenueVesting::Milestones { milestones } => milestones
+                .iter()
+                .filter(|(unlock_time, _)| *unlock_time <= time)
+                .map(|(_, share)| *share)
+                .sum::<Decimal>()
+                .min(Decimal::one()),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct PlatformFee {
+    pub rate: Decimal,
+    // Only the collector can change the fee
+    pub collector: Addr,
+}
+
+#[cw_serde]
+pub struct OtcFee {
+    pub rate: Decimal,
+    pub collector: Addr,
+}
+
+#[cw_serde]
+pub enum ReferralReward {
+    // Share of the payment in buy_denom, paid out with the revenue
+    Payment,
+    // Share of the tokens bought, vested on the sale batches
+    Tokens,
+}
+
+#[cw_serde]
+pub struct ReferralConfig {
+    pub rate: Decimal,
+    pub reward: ReferralReward,
+}
+
+#[cw_serde]
+pub enum Role {
+    // Starts sale and distribution phases
+    Operator,
+    // Updates revenue recipients and withdraws tokens
+    Treasurer,
+    // Edits the allowlist
+    AllowlistManager,
+}
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Operator => write!(f, "operator"),
+            Role::Treasurer => write!(f, "treasurer"),
+            Role::AllowlistManager => write!(f, "allowlist_manager"),
+        }
+    }
+}
+// Admin implicitly holds every role
+pub const ROLES: Map<(&str, &Addr), Empty> = Map::new("roles");
+
+pub const ALLOWLIST: Map<&Addr, Empty> = Map::new("allowlist");
+
+#[cw_serde]
+pub enum ConfigChange {
+    Price { price: Decimal },
+    RevenueRecipients { recipients: Vec<(String, Decimal)> },
+}
+
+#[cw_serde]
+pub struct PendingConfigChange {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub proposed_at: Timestamp,
+    pub executable_at: Timestamp,
+}
+pub const PENDING_CONFIG_CHANGES: Map<u64, PendingConfigChange> =
+    Map::new("pending_config_changes");
+// Id of the last proposed config change
+pub const CONFIG_CHANGE_COUNT: Item<u64> = Item::new("config_change_count");
+
+#[cw_serde]
+pub struct PendingAdmin {
+    pub address: Addr,
+    pub expires_at: Timestamp,
+}
+pub const PENDING_ADMIN: Item<PendingAdmin> = Item::new("pending_admin");
+
 #[cw_serde]
 pub enum Status {
     // Waiting
@@ -24,25 +179,118 @@ pub enum Status {
     // Distribution has started
     Distribution,
 }
-// implement to_string for Status
-impl ToString for Status {
-    fn to_string(&self) -> String {
+// implement Display (and so to_string) for Status
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Status::Pending => "pending".to_string(),
-            Status::Active => "active".to_string(),
-            Status::Distribution => "distribution".to_string(),
+            Status::Pending => write!(f, "pending"),
+            Status::Active => write!(f, "active"),
+            Status::Distribution => write!(f, "distribution"),
         }
     }
 }
 
+#[cw_serde]
+#[derive(Default)]
+pub struct PauseState {
+    pub buy_paused: bool,
+    pub claim_paused: bool,
+    // Each pause flag is ignored after its own expiry
+    pub buy_expires_at: Option<Timestamp>,
+    pub claim_expires_at: Option<Timestamp>,
+    // The guardian can not pause a flag again before this time
+    pub buy_cooldown_until: Option<Timestamp>,
+    pub claim_cooldown_until: Option<Timestamp>,
+}
+
+impl PauseState {
+    // Returns pause flags in effect at the given time
+    pub fn active_at(mut self, time: Timestamp) -> PauseState {
+        if self
+            .buy_expires_at
+            .is_some_and(|expires_at| expires_at <= time)
+        {
+            self.buy_paused = false;
+            self.buy_expires_at = None;
+        }
+        if self
+            .claim_expires_at
+            .is_some_and(|expires_at| expires_at <= time)
+        {
+            self.claim_paused = false;
+            self.claim_expires_at = None;
+        }
+        self
+    }
+}
+pub const PAUSE: Item<PauseState> = Item::new("pause");
+
 #[cw_serde]
 pub struct State {
     pub total_revenue: Uint128,
     pub total_sold: Uint128,
     pub status: Status,
+    // Revenue held by the contract until the cooling-off window of its purchase has passed
+    pub pending_revenue: Uint128,
+    pub unique_buyers: u64,
+    // Purchases that were not cancelled
+    pub purchase_count: u64,
+    // Largest amount paid in a single purchase
+    pub largest_purchase: Uint128,
+    pub total_claimed: Uint128,
+    // Tokens allocated by the admin through grants, kept out of total_sold
+    pub total_granted: Uint128,
+    // Tokens credited to referrers, kept out of total_sold
+    pub total_referral_tokens: Uint128,
+    // Revenue held for WithdrawRevenue when accumulate_revenue is set
+    pub accumulated_revenue: Uint128,
+    // Revenue paid out by WithdrawRevenue so far
+    pub revenue_withdrawn: Uint128,
 }
 pub const STATE: Item<State> = Item::new("state");
 
+// Length of a sales history bucket in seconds
+pub const SALES_BUCKET_DURATION: u64 = 3600;
+
+#[cw_serde]
+#[derive(Default)]
+pub struct SalesBucket {
+    pub revenue: Uint128,
+    pub tokens_sold: Uint128,
+    pub purchases: u64,
+}
+// Sales per bucket, keyed by bucket start in seconds
+pub const SALES_HISTORY: Map<u64, SalesBucket> = Map::new("sales_history");
+
+#[cw_serde]
+pub struct Purchase {
+    pub id: u64,
+    pub buyer: Addr,
+    pub amount_paid: Uint128,
+    pub amount_bought: Uint128,
+    // Sale price at the time of purchase
+    pub price: Decimal,
+    pub timestamp: Timestamp,
+    pub cancelled: bool,
+    pub referral: Option<ReferralCredit>,
+}
+
+#[cw_serde]
+pub struct ReferralCredit {
+    pub referrer: Addr,
+    // In buy_denom or tokens depending on the referral reward
+    pub reward: Uint128,
+}
+pub const PURCHASES: Map<u64, Purchase> = Map::new("purchases");
+// Purchase ids of each buyer
+pub const USER_PURCHASES: Map<(&Addr, u64), Empty> = Map::new("user_purchases");
+// Purchase ids by amount paid, keeps the largest purchase after cancellations
+pub const PURCHASES_BY_PAYMENT: Map<(u128, u64), Empty> = Map::new("purchases_by_payment");
+// Id of the last purchase
+pub const PURCHASE_COUNT: Item<u64> = Item::new("purchase_count");
+// Id of the last purchase whose revenue has been forwarded or refunded
+pub const REVENUE_CURSOR: Item<u64> = Item::new("revenue_cursor");
+
 #[cw_serde]
 pub struct Batch {
     pub amount: Uint128,
@@ -57,10 +305,119 @@ pub struct Position {
     pub total_bought: Uint128,
     pub total_paid: Uint128,
     pub total_claimed: Uint128,
+    // Price and time of the first purchase
     pub price: Decimal,
     pub timestamp: Timestamp,
+    // Volume weighted average price over all purchases
+    pub average_price: Decimal,
+    // Anyone can claim to the owner through ClaimFor if set
+    pub allow_claim_for: bool,
+    // Minted as an NFT with the address as token id, claim rights follow the NFT owner
+    pub tokenized: bool,
+    // Collateral lien granted to an approved lender, the position can not be moved while set
+    pub lien: Option<Lien>,
 
     // vector of batches
     pub batches: Bathces,
 }
 pub const POSITIONS: Map<Addr, Position> = Map::new("positions");
+// Tokens unlocking at each release time in nanoseconds, summed over all positions
+pub const UNLOCKS: Map<u64, Uint128> = Map::new("unlocks");
+
+#[cw_serde]
+pub struct ClaimReceipt {
+    pub id: u64,
+    pub owner: Addr,
+    pub recipient: Addr,
+    pub amount: Uint128,
+    // Release times of the batches paid out by this claim
+    pub release_times: Vec<Timestamp>,
+    pub timestamp: Timestamp,
+}
+pub const CLAIMS: Map<u64, ClaimReceipt> = Map::new("claims");
+// Claim ids of each position owner
+pub const USER_CLAIMS: Map<(&Addr, u64), Empty> = Map::new("user_claims");
+// Id of the last claim
+pub const CLAIM_COUNT: Item<u64> = Item::new("claim_count");
+
+#[cw_serde]
+pub struct Listing {
+    pub id: u64,
+    pub seller: Addr,
+    pub amount: Uint128,
+    // Price per token in buy_denom
+    pub price: Decimal,
+    // Escrowed batches and the share of the seller's total_paid they carry
+    pub batches: Bathces,
+    pub seller_paid: Uint128,
+    pub created_at: Timestamp,
+}
+pub const LISTINGS: Map<u64, Listing> = Map::new("listings");
+// Listing ids of each seller
+pub const SELLER_LISTINGS: Map<(&Addr, u64), Empty> = Map::new("seller_listings");
+// Id of the last listing
+pub const LISTING_COUNT: Item<u64> = Item::new("listing_count");
+
+#[cw_serde]
+#[derive(Default)]
+pub struct ReferralStats {
+    // Buyers that recorded this referrer
+    pub referrals: u64,
+    pub purchases: u64,
+    // Payments of referred purchases in buy_denom
+    pub volume: Uint128,
+    // Rewards credited, in buy_denom or tokens depending on the referral reward
+    pub rewards: Uint128,
+}
+// Referrer of each buyer, recorded with the first purchase
+pub const REFERRERS: Map<&Addr, Addr> = Map::new("referrers");
+pub const REFERRAL_STATS: Map<&Addr, ReferralStats> = Map::new("referral_stats");
+
+// Staking contracts tokens can be claimed into, with the hook message template sent along
+pub const STAKING_CONTRACTS: Map<&Addr, String> = Map::new("staking_contracts");
+// Replaced by the position owner address in hook message templates
+pub const STAKING_HOOK_OWNER: &str = "{owner}";
+
+#[cw_serde]
+pub struct Lien {
+    pub lender: Addr,
+    // Claims are sent to the lender if set, otherwise they are blocked
+    pub redirect_claims: bool,
+    pub granted_at: Timestamp,
+}
+// Lender contracts holders can grant liens to
+pub const LENDERS: Map<&Addr, Empty> = Map::new("lenders");
+
+#[cw_serde]
+pub struct Grant {
+    pub address: Addr,
+    pub amount: Uint128,
+    pub granted_at: Timestamp,
+    // Unvested tokens can be clawed back by the admin if set
+    pub revocable: bool,
+    // Set once revoked, the position is closed with only its vested tokens left
+    pub revoked_at: Option<Timestamp>,
+    pub clawed_back: Uint128,
+}
+// Allocations recorded by the admin without payment, their positions do not take purchases
+pub const GRANTS: Map<&Addr, Grant> = Map::new("grants");
+
+impl Position {
+    // Recalculates average_price from total_paid and total_bought
+    pub fn update_average_price(&mut self) {
+        self.average_price = if self.total_bought.is_zero() {
+            Decimal::zero()
+        } else {
+            Decimal::from_ratio(self.total_paid, self.total_bought)
+        };
+    }
+
+    // Tokens that are still waiting to be claimed, vested or not
+    pub fn unclaimed(&self) -> Uint128 {
+        self.batches
+            .iter()
+            .filter(|batch| !batch.released)
+            .map(|batch| batch.amount)
+            .sum()
+    }
+}
@@ -1,23 +1,32 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use std::str::FromStr;
 
     use cosmwasm_std::testing::{
-        mock_dependencies, mock_dependencies_with_balance, mock_env, mock_info,
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
     };
     use cosmwasm_std::{
-        from_binary, to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, MessageInfo, Timestamp,
-        Uint128, Uint256, WasmMsg,
+        from_binary, to_binary, Addr, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, OwnedDeps,
+        SystemResult, Timestamp, Uint128, WasmMsg,
     };
-    use cw20::Cw20ExecuteMsg;
+    use cw20::{BalanceResponse, Cw20ExecuteMsg};
     use cw_utils::PaymentError;
 
-    use crate::contract::{execute, instantiate, query};
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::migrations::v0_1;
     use crate::msg::{
-        ExecuteMsg, InstantiateMsg, QueryConfigResponse, QueryMsg, QueryPositionResponse,
-        QueryStateResponse,
+        AllPositionsResponse, ClaimHistoryResponse, ClaimableResponse, DistributeMaturedResponse,
+        ExecuteMsg, ExportPositionsResponse, GrantsResponse, InstantiateMsg, ListingsResponse,
+        MigrateMsg, PositionValueResponse, PurchaseHistoryResponse, QueryAllowlistedResponse,
+        QueryConfigResponse, QueryMsg, QueryPauseResponse, QueryPendingConfigChangesResponse,
+        QueryPositionResponse, QueryRoleHoldersResponse, QueryStateResponse,
+        RevenueVestingResponse, SaleStatsResponse, SalesHistoryResponse, SimulateBuyResponse,
+        UnlockBucket, UnlockScheduleResponse,
+    };
+    use crate::state::{
+        Batch, ConfigChange, ReferralReward, ReferralStats, RevenueVesting, Role, Status,
     };
-    use crate::state::Batch;
     use crate::ContractError;
 
     fn default_init_msg() -> InstantiateMsg {
@@ -26,13 +35,43 @@ mod tests {
             admin: None,
             batch_duration: Uint128::from(100u128),
             batch_amount: Uint128::from(10u128),
-            revenue_collector: "revenue_collector".to_string(),
+            revenue_recipients: vec![("revenue_collector".to_string(), Decimal::one())],
+            platform_fee: None,
+            platform_fee_collector: None,
+            accumulate_revenue: None,
+            revenue_vesting: None,
             price: Decimal::from_str("0.1").unwrap(),
             buy_denom: "ujuno".to_string(),
             sell_denom: "token".to_string(),
-            first_batch_release_time: first_batch_release_time,
+            first_batch_release_time,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
         }
     }
+
+    fn bank_send(to: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![Coin::new(amount, "ujuno")],
+        })
+    }
+
+    // Balance the mocked cw20 returns for the contract
+    fn mock_token_balance(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, balance: u128) {
+        deps.querier.update_wasm(move |_| {
+            SystemResult::Ok(ContractResult::Ok(
+                to_binary(&BalanceResponse {
+                    balance: Uint128::from(balance),
+                })
+                .unwrap(),
+            ))
+        });
+    }
     #[test]
     pub fn test_proper_init() {
         let mut deps = mock_dependencies();
@@ -48,8 +87,8 @@ mod tests {
         assert_eq!("100".to_string(), res.attributes[2].value);
         assert_eq!("batch_amount".to_string(), res.attributes[3].key);
         assert_eq!("10".to_string(), res.attributes[3].value);
-        assert_eq!("revenue_collector".to_string(), res.attributes[4].key);
-        assert_eq!("revenue_collector".to_string(), res.attributes[4].value);
+        assert_eq!("revenue_recipients".to_string(), res.attributes[4].key);
+        assert_eq!("revenue_collector:1".to_string(), res.attributes[4].value);
         assert_eq!("price".to_string(), res.attributes[5].key);
         assert_eq!("0.1".to_string(), res.attributes[5].value);
         assert_eq!("buy_denom".to_string(), res.attributes[6].key);
@@ -79,42 +118,42 @@ mod tests {
         let mut deps = mock_dependencies();
         let init_msg = default_init_msg();
         let info = mock_info("creator", &[]);
-        let res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+        instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
 
         // Try buying before sale start
         let info = mock_info("buyer", &[]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
         assert_eq!(ContractError::SaleNotActive {}, res);
         // acticate sale
         let info = mock_info("creator", &[]);
         let msg = ExecuteMsg::StartSale {};
-        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         // Try buying with without funds
         let info = mock_info("buyer", &[]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
         assert_eq!(ContractError::Payment(PaymentError::NoFunds {}), res);
 
         // Try buying with wrong denom
         let info = mock_info("buyer", &[Coin::new(10, "wrong_denom")]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
         assert_eq!(
-            ContractError::Payment(PaymentError::MissingDenom(("ujuno".to_string()))),
+            ContractError::Payment(PaymentError::MissingDenom("ujuno".to_string())),
             res
         );
 
         // Try buying with multiple coins
         let info = mock_info("buyer", &[Coin::new(10, "ujuno"), Coin::new(10, "ujuno2")]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
         assert_eq!(ContractError::Payment(PaymentError::MultipleDenoms {}), res);
 
         // Happy path
         let info = mock_info("buyer", &[Coin::new(10, "ujuno")]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         // check if the payment was sent to revenue collector
         assert_eq!(
@@ -206,7 +245,7 @@ mod tests {
 
         // Try buying again
         let info = mock_info("buyer", &[Coin::new(877, "ujuno")]);
-        let msg = ExecuteMsg::Buy {};
+        let msg = ExecuteMsg::Buy { referrer: None };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         // check if the payment was sent to revenue collector
         assert_eq!(
@@ -245,14 +284,17 @@ mod tests {
         // start distribution
         let info = mock_info("creator", &[]);
         let msg = ExecuteMsg::StartDistribution {};
-        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         // try claiming
         let mut env = mock_env();
         // First batch should be released
         env.block.time = Timestamp::from_nanos(100000000000 + 1);
         let info = mock_info("buyer", &[]);
-        let msg = ExecuteMsg::Claim {};
+        let msg = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
         let res = execute(deps.as_mut(), env, info, msg).unwrap();
         // check if the cw20 tokens were sent to user
         assert_eq!(
@@ -279,12 +321,14 @@ mod tests {
             .unwrap(),
         )
         .unwrap();
-        // Now User has 9 batches of cw20 tokens
-        assert_eq!(position.batches.len(), 9);
+        // Full schedule is kept, first batch is released
+        assert_eq!(position.batches.len(), 10);
         assert_eq!(position.total_claimed, Uint128::from(887u128));
-        assert_eq!(position.batches[0].amount, Uint128::from(887u128));
+        assert!(position.batches[0].released);
+        assert!(!position.batches[1].released);
+        assert_eq!(position.batches[1].amount, Uint128::from(887u128));
         assert_eq!(
-            position.batches[0].release_time,
+            position.batches[1].release_time,
             Timestamp::from_nanos(100000000100)
         );
 
@@ -292,7 +336,10 @@ mod tests {
         let mut env = mock_env();
         env.block.time = Timestamp::from_nanos(100000000900 + 1);
         let info = mock_info("buyer", &[]);
-        let msg = ExecuteMsg::Claim {};
+        let msg = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
         let res = execute(deps.as_mut(), env, info, msg).unwrap();
         // check if the cw20 tokens were sent to user
         assert_eq!(
@@ -319,8 +366,8 @@ mod tests {
             .unwrap(),
         )
         .unwrap();
-        // Now User has 0 batches of cw20 tokens
-        assert_eq!(position.batches.len(), 0);
+        // Every batch is released
+        assert!(position.batches.iter().all(|batch| batch.released));
         assert_eq!(position.total_claimed, Uint128::from(8870u128));
     }
 
@@ -331,18 +378,25 @@ mod tests {
         let info = mock_info("creator", &[]);
         let env = mock_env();
         let msg = default_init_msg();
-        let res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
         // random update config
         let info = mock_info("random", &[]);
         let msg = ExecuteMsg::UpdateConfig {
-            admin: None,
             batch_duration: Some(Uint128::from(12u128)),
-            revenue_collector: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
             buy_denom: None,
             sell_denom: None,
             batch_amount: None,
             price: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
         };
         let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
         assert_eq!(res.unwrap_err(), ContractError::Unauthorized {});
@@ -350,15 +404,22 @@ mod tests {
         // update config
         let info = mock_info("creator", &[]);
         let msg = ExecuteMsg::UpdateConfig {
-            admin: None,
             batch_duration: Some(Uint128::from(12u128)),
-            revenue_collector: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
             buy_denom: None,
             sell_denom: None,
             batch_amount: None,
             price: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
         };
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
         // check config
         let config: QueryConfigResponse =
             from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::QueryConfig {}).unwrap())
@@ -368,20 +429,3694 @@ mod tests {
         // start sale
         let info = mock_info("creator", &[]);
         let msg = ExecuteMsg::StartSale {};
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
 
         // update config
         let info = mock_info("creator", &[]);
         let msg = ExecuteMsg::UpdateConfig {
-            admin: None,
             batch_duration: Some(Uint128::from(12u128)),
-            revenue_collector: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
             buy_denom: None,
             sell_denom: None,
             batch_amount: None,
             price: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
         };
         let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap_err();
         assert_eq!(res, ContractError::SaleNotPending {});
     }
+
+    #[test]
+    pub fn test_cancel_purchase() {
+        let mut deps = mock_dependencies();
+        let mut init_msg = default_init_msg();
+        // 1 hour cooling-off period
+        init_msg.cooling_off_period = Some(3600);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+
+        // Buy twice, revenue is held by the contract
+        let info = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!("purchase_id", res.attributes[3].key);
+        assert_eq!("1", res.attributes[3].value);
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(1800);
+        let info = mock_info("buyer", &[Coin::new(50, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.pending_revenue, Uint128::from(60u128));
+
+        // Nothing can be forwarded while the first purchase is in its cooling-off period
+        let info = mock_info("anyone", &[]);
+        let msg = ExecuteMsg::ForwardRevenue { limit: None };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert_eq!(ContractError::NoRevenueToForward {}, err);
+
+        // Only the buyer can cancel
+        let info = mock_info("random", &[]);
+        let msg = ExecuteMsg::CancelPurchase { purchase_id: 2 };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // Cancel second purchase
+        let info = mock_info("buyer", &[]);
+        let msg = ExecuteMsg::CancelPurchase { purchase_id: 2 };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "buyer".to_string(),
+                amount: vec![Coin::new(50, "ujuno")],
+            }),
+            res.messages[0].msg
+        );
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert_eq!(ContractError::PurchaseCancelled {}, err);
+
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(100u128));
+        assert_eq!(position.total_paid, Uint128::from(10u128));
+        assert_eq!(position.batches[0].amount, Uint128::from(10u128));
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_sold, Uint128::from(100u128));
+        assert_eq!(state.total_revenue, Uint128::from(10u128));
+        assert_eq!(state.pending_revenue, Uint128::from(10u128));
+
+        // A buyer whose only purchase is cancelled is no longer counted
+        let info = mock_info("buyer2", &[Coin::new(30, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        let msg = ExecuteMsg::CancelPurchase { purchase_id: 3 };
+        execute(deps.as_mut(), env.clone(), mock_info("buyer2", &[]), msg).unwrap();
+        let stats: SaleStatsResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::SaleStats {}).unwrap())
+                .unwrap();
+        assert_eq!(stats.unique_buyers, 1);
+        assert_eq!(stats.purchase_count, 1);
+        assert_eq!(stats.largest_purchase, Uint128::from(10u128));
+
+        // First purchase can not be cancelled after the window
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let info = mock_info("buyer", &[]);
+        let msg = ExecuteMsg::CancelPurchase { purchase_id: 1 };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert_eq!(ContractError::CoolingOffPeriodPassed {}, err);
+
+        // Revenue of the first purchase can now be forwarded
+        let info = mock_info("anyone", &[]);
+        let msg = ExecuteMsg::ForwardRevenue { limit: None };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "revenue_collector".to_string(),
+                amount: vec![Coin::new(10, "ujuno")],
+            }),
+            res.messages[0].msg
+        );
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::NoRevenueToForward {}, err);
+
+        // Cancelled purchases do not hold up the ones after them
+        for amount in [20, 30] {
+            let buyer = mock_info("buyer", &[Coin::new(amount, "ujuno")]);
+            let msg = ExecuteMsg::Buy { referrer: None };
+            execute(deps.as_mut(), env.clone(), buyer, msg).unwrap();
+        }
+        let msg = ExecuteMsg::CancelPurchase { purchase_id: 4 };
+        execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), msg).unwrap();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let msg = ExecuteMsg::ForwardRevenue { limit: Some(2) };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(res.attributes[2].value, "3");
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "revenue_collector".to_string(),
+                amount: vec![Coin::new(30, "ujuno")],
+            }),
+            res.messages[0].msg
+        );
+    }
+
+    #[test]
+    pub fn test_pause() {
+        let mut deps = mock_dependencies();
+        let mut init_msg = default_init_msg();
+        init_msg.guardian = Some("guardian".to_string());
+        init_msg.max_pause_duration = Some(86400);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+
+        // Only guardian or admin can pause
+        let info = mock_info("random", &[]);
+        let msg = ExecuteMsg::Pause {
+            buy: true,
+            claim: false,
+            duration: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // Guardian can not pause longer than max_pause_duration
+        let info = mock_info("guardian", &[]);
+        let long_pause = ExecuteMsg::Pause {
+            buy: true,
+            claim: false,
+            duration: Some(86401),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), long_pause).unwrap_err();
+        assert_eq!(ContractError::InvalidPauseDuration { max: 86400 }, err);
+
+        // Pause buys, status does not change
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Paused {}, err);
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.status, "active".to_string());
+        let pause: QueryPauseResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryPause {}).unwrap())
+                .unwrap();
+        assert!(pause.buy_paused);
+        assert!(!pause.claim_paused);
+        assert_eq!(
+            pause.buy_expires_at,
+            Some(mock_env().block.time.plus_seconds(86400))
+        );
+        assert_eq!(pause.claim_expires_at, None);
+
+        // Pause expires on its own
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(86400);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            buyer.clone(),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        let pause: QueryPauseResponse =
+            from_binary(&query(deps.as_ref(), env, QueryMsg::QueryPause {}).unwrap()).unwrap();
+        assert!(!pause.buy_paused);
+
+        // Admin pauses without expiry and unpauses
+        let info = mock_info("creator", &[]);
+        let msg = ExecuteMsg::Pause {
+            buy: true,
+            claim: true,
+            duration: None,
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::Unpause {
+            buy: true,
+            claim: false,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        let pause: QueryPauseResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryPause {}).unwrap())
+                .unwrap();
+        assert!(!pause.buy_paused);
+        assert!(pause.claim_paused);
+
+        // Claims are paused
+        let info = mock_info("creator", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let info = mock_info("buyer", &[]);
+        let err = execute(
+            deps.as_mut(),
+            env,
+            info,
+            ExecuteMsg::Claim {
+                recipient: None,
+                token_id: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Paused {}, err);
+
+        // Guardian has to wait as long as its first pause lasted before pausing buys again
+        let guardian = mock_info("guardian", &[]);
+        let msg = ExecuteMsg::Pause {
+            buy: true,
+            claim: false,
+            duration: Some(3600),
+        };
+        let start = mock_env().block.time.plus_seconds(2 * 86400);
+        let mut env = mock_env();
+        env.block.time = start.minus_seconds(1);
+        let err = execute(deps.as_mut(), env.clone(), guardian.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::PauseCooldown {}, err);
+        env.block.time = start;
+        execute(deps.as_mut(), env.clone(), guardian.clone(), msg.clone()).unwrap();
+
+        // Pausing buys leaves the claim pause without expiry
+        env.block.time = start.plus_seconds(3600);
+        let pause: QueryPauseResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::QueryPause {}).unwrap())
+                .unwrap();
+        assert!(!pause.buy_paused);
+        assert!(pause.claim_paused);
+        assert_eq!(pause.claim_expires_at, None);
+
+        // Guardian can not keep buys paused by pausing again
+        env.block.time = start.plus_seconds(3599);
+        let err = execute(deps.as_mut(), env.clone(), guardian.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::PauseCooldown {}, err);
+        env.block.time = start.plus_seconds(3600 + 86400 - 1);
+        let err = execute(deps.as_mut(), env.clone(), guardian.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::PauseCooldown {}, err);
+        env.block.time = start.plus_seconds(3600 + 86400);
+        execute(deps.as_mut(), env, guardian, msg).unwrap();
+    }
+
+    #[test]
+    pub fn test_admin_transfer() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, default_init_msg()).unwrap();
+
+        // Only admin can propose
+        let msg = ExecuteMsg::ProposeAdmin {
+            address: "new_admin".to_string(),
+            expires_in: 3600,
+        };
+        let info = mock_info("random", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(config.admin, Some("creator".to_string()));
+        assert_eq!(config.pending_admin, Some("new_admin".to_string()));
+        assert_eq!(
+            config.pending_admin_expires_at,
+            Some(mock_env().block.time.plus_seconds(3600))
+        );
+
+        // Only proposed admin can accept and only before expiry
+        let info = mock_info("random", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::AcceptAdmin {}).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let info = mock_info("new_admin", &[]);
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::AcceptAdmin {}).unwrap_err();
+        assert_eq!(ContractError::AdminProposalExpired {}, err);
+
+        // Cancel proposal
+        let info = mock_info("creator", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::CancelAdminProposal {},
+        )
+        .unwrap();
+        let new_admin = mock_info("new_admin", &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::NoAdminProposal {}, err);
+
+        // Propose again and accept
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap();
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(config.admin, Some("new_admin".to_string()));
+        assert_eq!(config.pending_admin, None);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // Admin can only renounce once distribution has started
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::RenounceAdmin {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::SaleNotDistribution {}, err);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        // Renounce admin
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            new_admin.clone(),
+            ExecuteMsg::RenounceAdmin {},
+        )
+        .unwrap();
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(config.admin, None);
+        let msg = ExecuteMsg::UpdateLenders {
+            add: vec!["lender".to_string()],
+            remove: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), new_admin, msg).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+    }
+
+    #[test]
+    pub fn test_roles() {
+        let mut deps = mock_dependencies();
+        let mut init_msg = default_init_msg();
+        init_msg.allowlist_enabled = Some(true);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        // Only admin can grant roles
+        let msg = ExecuteMsg::GrantRole {
+            role: Role::Operator,
+            address: "operator".to_string(),
+        };
+        let info = mock_info("random", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let admin = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        for (role, address) in [
+            (Role::Treasurer, "treasurer"),
+            (Role::AllowlistManager, "allowlist_manager"),
+        ] {
+            let msg = ExecuteMsg::GrantRole {
+                role,
+                address: address.to_string(),
+            };
+            execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        }
+        let holders: QueryRoleHoldersResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryRoleHolders {
+                    role: Role::Operator,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(holders.holders, vec!["operator".to_string()]);
+
+        // Treasurer can change revenue recipients but nothing else
+        let treasurer = mock_info("treasurer", &[]);
+        let mut msg = ExecuteMsg::UpdateConfig {
+            batch_duration: None,
+            batch_amount: None,
+            revenue_recipients: Some(vec![("new_collector".to_string(), Decimal::one())]),
+            accumulate_revenue: None,
+            price: None,
+            buy_denom: None,
+            sell_denom: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
+        };
+        execute(deps.as_mut(), mock_env(), treasurer.clone(), msg.clone()).unwrap();
+        if let ExecuteMsg::UpdateConfig { price, .. } = &mut msg {
+            *price = Some(Decimal::from_str("0.2").unwrap());
+        }
+        let err = execute(deps.as_mut(), mock_env(), treasurer.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // Treasurer can not start the sale, operator can
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            treasurer.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let operator = mock_info("operator", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            operator,
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+
+        // Buyer has to be allowlisted
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::NotAllowlisted {}, err);
+        let msg = ExecuteMsg::UpdateAllowlist {
+            add: vec!["buyer".to_string()],
+            remove: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let info = mock_info("allowlist_manager", &[]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let allowlisted: QueryAllowlistedResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryAllowlisted {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(allowlisted.allowlisted);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "new_collector".to_string(),
+                amount: vec![Coin::new(10, "ujuno")],
+            }),
+            res.messages[0].msg
+        );
+
+        // Revoked operator can not start distribution
+        let msg = ExecuteMsg::RevokeRole {
+            role: Role::Operator,
+            address: "operator".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let operator = mock_info("operator", &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            operator,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // Roles end when the admin renounces
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::RenounceAdmin {},
+        )
+        .unwrap();
+        let msg = ExecuteMsg::UpdateAllowlist {
+            add: vec!["buyer2".to_string()],
+            remove: vec![],
+        };
+        let info = mock_info("allowlist_manager", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let holders: QueryRoleHoldersResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryRoleHolders {
+                    role: Role::Treasurer,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(holders.holders.is_empty());
+    }
+
+    #[test]
+    pub fn test_config_timelock() {
+        let mut deps = mock_dependencies();
+        let mut init_msg = default_init_msg();
+        // 1 day timelock
+        init_msg.config_timelock = Some(86400);
+        let admin = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), init_msg).unwrap();
+        let msg = ExecuteMsg::GrantRole {
+            role: Role::Treasurer,
+            address: "treasurer".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+
+        // Price can not be updated directly once the sale is active
+        let msg = ExecuteMsg::UpdateConfig {
+            batch_duration: None,
+            batch_amount: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
+            price: Some(Decimal::from_str("0.2").unwrap()),
+            buy_denom: None,
+            sell_denom: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::SaleNotPending {}, err);
+
+        // Only treasurer or admin can propose a revenue recipients change
+        let msg = ExecuteMsg::ProposeConfigChange {
+            change: ConfigChange::RevenueRecipients {
+                recipients: vec![("new_collector".to_string(), Decimal::one())],
+            },
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("random", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let treasurer = mock_info("treasurer", &[]);
+        execute(deps.as_mut(), mock_env(), treasurer.clone(), msg).unwrap();
+        // Invalid price is rejected at proposal time
+        let msg = ExecuteMsg::ProposeConfigChange {
+            change: ConfigChange::Price {
+                price: Decimal::zero(),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::InvalidPrice {}, err);
+        let msg = ExecuteMsg::ProposeConfigChange {
+            change: ConfigChange::Price {
+                price: Decimal::from_str("0.2").unwrap(),
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let changes: QueryPendingConfigChangesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPendingConfigChanges {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(changes.changes.len(), 2);
+        let executable_at = mock_env().block.time.plus_seconds(86400);
+        assert_eq!(changes.changes[0].executable_at, executable_at);
+
+        // Can not execute before the timelock passes
+        let msg = ExecuteMsg::ExecuteConfigChange { id: 1 };
+        let err = execute(deps.as_mut(), mock_env(), treasurer.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::TimelockNotExpired { executable_at }, err);
+        let mut env = mock_env();
+        env.block.time = executable_at;
+        execute(deps.as_mut(), env.clone(), treasurer.clone(), msg).unwrap();
+
+        // Treasurer can not touch the price change, admin cancels it
+        let msg = ExecuteMsg::CancelConfigChange { id: 2 };
+        let err = execute(deps.as_mut(), env.clone(), treasurer, msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), env.clone(), admin, msg).unwrap();
+
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(
+            config.revenue_recipients[0].address,
+            Addr::unchecked("new_collector")
+        );
+        assert_eq!(config.price, Decimal::from_str("0.1").unwrap());
+        let changes: QueryPendingConfigChangesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::QueryPendingConfigChanges {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(changes.changes.is_empty());
+    }
+
+    #[test]
+    pub fn test_migrate() {
+        let mut deps = mock_dependencies();
+        // Fill the store the way v0.1 did, without a contract version
+        let old_config = v0_1::Config {
+            admin: Addr::unchecked("creator"),
+            batch_duration: Uint128::from(100u128),
+            batch_amount: Uint128::from(10u128),
+            revenue_collector: Addr::unchecked("revenue_collector"),
+            price: Decimal::from_str("0.1").unwrap(),
+            buy_denom: "ujuno".to_string(),
+            sell_denom: Addr::unchecked("token"),
+            first_batch_release_time: Timestamp::from_nanos(100000000000),
+        };
+        v0_1::CONFIG.save(&mut deps.storage, &old_config).unwrap();
+        let old_state = v0_1::State {
+            total_revenue: Uint128::from(10u128),
+            total_sold: Uint128::from(100u128),
+            // v0.1 only allowed claims during distribution
+            status: Status::Distribution,
+        };
+        v0_1::STATE.save(&mut deps.storage, &old_state).unwrap();
+        let old_position = v0_1::Position {
+            address: Addr::unchecked("buyer"),
+            total_bought: Uint128::from(100u128),
+            total_paid: Uint128::from(10u128),
+            // v0.1 deleted the 9 claimed batches
+            total_claimed: Uint128::from(90u128),
+            price: Decimal::from_str("0.1").unwrap(),
+            timestamp: mock_env().block.time,
+            batches: vec![Batch {
+                amount: Uint128::from(10u128),
+                release_time: Timestamp::from_nanos(100000000900),
+                released: false,
+            }],
+        };
+        v0_1::POSITIONS
+            .save(&mut deps.storage, Addr::unchecked("buyer"), &old_position)
+            .unwrap();
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!("previous_version".to_string(), res.attributes[1].key);
+        assert_eq!("0.1.0".to_string(), res.attributes[1].value);
+        let version = cw2::get_contract_version(&deps.storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(config.admin, Some("creator".to_string()));
+        assert_eq!(config.price, Decimal::from_str("0.1").unwrap());
+        assert_eq!(config.cooling_off_period, 0);
+        assert_eq!(config.config_timelock, 86400);
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.status, "distribution".to_string());
+        assert_eq!(state.total_sold, Uint128::from(100u128));
+        assert_eq!(state.pending_revenue, Uint128::zero());
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(100u128));
+        assert_eq!(position.average_price, Decimal::from_str("0.1").unwrap());
+        assert_eq!(position.batches.len(), 10);
+        assert_eq!(
+            position.batches[0],
+            Batch {
+                amount: Uint128::from(10u128),
+                release_time: Timestamp::from_nanos(100000000000),
+                released: true,
+            }
+        );
+        assert_eq!(position.batches[9], old_position.batches[0]);
+
+        // Migrated contract keeps working, only the last batch is left to claim
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000900 + 1);
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        let res = execute(deps.as_mut(), env, mock_info("buyer", &[]), claim).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "buyer".to_string(),
+                    amount: Uint128::from(10u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+
+        // Downgrades are refused
+        cw2::set_contract_version(&mut deps.storage, "crates.io:launchpad", "9.9.9").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            ContractError::CannotMigrate {
+                previous_contract: "crates.io:launchpad".to_string(),
+                previous_version: "9.9.9".to_string(),
+            },
+            err
+        );
+        // Other contracts are refused
+        cw2::set_contract_version(&mut deps.storage, "crates.io:other", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            ContractError::CannotMigrate {
+                previous_contract: "crates.io:other".to_string(),
+                previous_version: "0.1.0".to_string(),
+            },
+            err
+        );
+    }
+
+    #[test]
+    pub fn test_all_positions() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+        for (buyer, amount) in [("buyer1", 10), ("buyer2", 20), ("buyer3", 30)] {
+            let info = mock_info(buyer, &[Coin::new(amount, "ujuno")]);
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::Buy { referrer: None },
+            )
+            .unwrap();
+        }
+
+        let page: AllPositionsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::AllPositions {
+                    start_after: None,
+                    limit: Some(2),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(page.positions.len(), 2);
+        assert_eq!(page.positions[0].address, "buyer1".to_string());
+        assert_eq!(page.positions[1].total_bought, Uint128::from(200u128));
+        assert_eq!(page.positions[1].unclaimed, Uint128::from(200u128));
+        let page: AllPositionsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::AllPositions {
+                    start_after: Some("buyer2".to_string()),
+                    limit: Some(2),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(page.positions.len(), 1);
+        assert_eq!(page.positions[0].address, "buyer3".to_string());
+        assert_eq!(page.positions[0].total_paid, Uint128::from(30u128));
+
+        let export: ExportPositionsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ExportPositions {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(export.positions.len(), 3);
+        assert_eq!(export.positions[2].address, Addr::unchecked("buyer3"));
+        assert_eq!(export.positions[2].batches.len(), 10);
+        assert_eq!(export.positions[2].batches[0].amount, Uint128::from(30u128));
+    }
+
+    #[test]
+    pub fn test_claimable_and_unlock_schedule() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+        for (buyer, amount) in [("buyer1", 10), ("buyer2", 20)] {
+            let info = mock_info(buyer, &[Coin::new(amount, "ujuno")]);
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::Buy { referrer: None },
+            )
+            .unwrap();
+        }
+
+        // Nothing is claimable before the first release
+        let claimable: ClaimableResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Claimable {
+                    address: "buyer1".to_string(),
+                    at_time: Some(Timestamp::from_nanos(100000000000)),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claimable.amount, Uint128::zero());
+        assert_eq!(
+            claimable.next_unlock_time,
+            Some(Timestamp::from_nanos(100000000000))
+        );
+        assert_eq!(claimable.next_unlock_amount, Uint128::from(10u128));
+        assert_eq!(
+            claimable.fully_vested_time,
+            Some(Timestamp::from_nanos(100000000900))
+        );
+
+        // First two batches are claimable
+        let claimable: ClaimableResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Claimable {
+                    address: "buyer2".to_string(),
+                    at_time: Some(Timestamp::from_nanos(100000000100 + 1)),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claimable.amount, Uint128::from(40u128));
+        assert_eq!(
+            claimable.next_unlock_time,
+            Some(Timestamp::from_nanos(100000000200))
+        );
+        assert_eq!(claimable.next_unlock_amount, Uint128::from(20u128));
+
+        // Contract-wide schedule
+        let schedule: UnlockScheduleResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::UnlockSchedule {
+                    start: None,
+                    end: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(schedule.unlocks.len(), 10);
+        assert_eq!(schedule.total, Uint128::from(300u128));
+        assert_eq!(schedule.unlocks[0].amount, Uint128::from(30u128));
+        let schedule: UnlockScheduleResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::UnlockSchedule {
+                    start: Some(Timestamp::from_nanos(100000000100)),
+                    end: Some(Timestamp::from_nanos(100000000300)),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(schedule.unlocks.len(), 2);
+        assert_eq!(
+            schedule.unlocks[0].release_time,
+            Timestamp::from_nanos(100000000100)
+        );
+        assert_eq!(schedule.total, Uint128::from(60u128));
+    }
+
+    #[test]
+    pub fn test_simulate_buy() {
+        let mut deps = mock_dependencies();
+        let mut init_msg = default_init_msg();
+        init_msg.price = Decimal::from_str("3").unwrap();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+
+        // Simulation fails the same way buy does
+        let msg = QueryMsg::SimulateBuy {
+            buyer: "buyer".to_string(),
+            funds: vec![Coin::new(10, "ujuno")],
+            referrer: None,
+        };
+        let quote: SimulateBuyResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(
+            quote.error,
+            Some(ContractError::SaleNotActive {}.to_string())
+        );
+        assert_eq!(quote.buy_amount, Uint128::zero());
+
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+        let quote: SimulateBuyResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(quote.error, None);
+        assert_eq!(quote.buy_amount, Uint128::from(3u128));
+        assert_eq!(quote.amount_paid, Uint128::from(9u128));
+        assert_eq!(quote.refund, Uint128::from(1u128));
+        assert_eq!(quote.effective_price, Some(Decimal::from_str("3").unwrap()));
+        assert_eq!(quote.batches.len(), 10);
+
+        // Buy matches the quote and refunds the dust
+        let info = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "revenue_collector".to_string(),
+                amount: vec![Coin::new(9, "ujuno")],
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "buyer".to_string(),
+                amount: vec![Coin::new(1, "ujuno")],
+            }),
+            res.messages[1].msg
+        );
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, quote.buy_amount);
+        assert_eq!(position.total_paid, quote.amount_paid);
+        assert_eq!(position.batches, quote.batches);
+
+        // Too little funds
+        let msg = QueryMsg::SimulateBuy {
+            buyer: "buyer".to_string(),
+            funds: vec![Coin::new(2, "ujuno")],
+            referrer: None,
+        };
+        let quote: SimulateBuyResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            quote.error,
+            Some(ContractError::ZeroBuyAmount {}.to_string())
+        );
+    }
+
+    #[test]
+    pub fn test_sale_stats() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+
+        // mock_env time is 1571797419 seconds, its hour starts at 1571796000
+        let hour_start = 1571796000u64;
+        for (buyer, amount, seconds) in
+            [("buyer1", 10, 0), ("buyer2", 30, 100), ("buyer1", 20, 3600)]
+        {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            let info = mock_info(buyer, &[Coin::new(amount, "ujuno")]);
+            execute(deps.as_mut(), env, info, ExecuteMsg::Buy { referrer: None }).unwrap();
+        }
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let info = mock_info("buyer2", &[]);
+        execute(
+            deps.as_mut(),
+            env,
+            info,
+            ExecuteMsg::Claim {
+                recipient: None,
+                token_id: None,
+            },
+        )
+        .unwrap();
+
+        let stats: SaleStatsResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::SaleStats {}).unwrap())
+                .unwrap();
+        assert_eq!(stats.total_revenue, Uint128::from(60u128));
+        assert_eq!(stats.total_sold, Uint128::from(600u128));
+        assert_eq!(stats.unique_buyers, 2);
+        assert_eq!(stats.purchase_count, 3);
+        assert_eq!(stats.largest_purchase, Uint128::from(30u128));
+        assert_eq!(stats.total_claimed, Uint128::from(30u128));
+
+        let history: SalesHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SalesHistory {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.bucket_duration, 3600);
+        assert_eq!(history.buckets.len(), 2);
+        assert_eq!(history.buckets[0].start, hour_start);
+        assert_eq!(history.buckets[0].revenue, Uint128::from(40u128));
+        assert_eq!(history.buckets[0].tokens_sold, Uint128::from(400u128));
+        assert_eq!(history.buckets[0].purchases, 2);
+        assert_eq!(history.buckets[1].start, hour_start + 3600);
+        let history: SalesHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SalesHistory {
+                    start_after: Some(hour_start),
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.buckets.len(), 1);
+        assert_eq!(history.buckets[0].revenue, Uint128::from(20u128));
+    }
+
+    #[test]
+    pub fn test_purchase_history() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[3].key, "purchase_id");
+        assert_eq!(res.attributes[3].value, "1");
+        let other = mock_info("other", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            other,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+
+        // price change through the timelock does not rewrite earlier receipts
+        let msg = ExecuteMsg::ProposeConfigChange {
+            change: ConfigChange::Price {
+                price: Decimal::from_str("0.2").unwrap(),
+            },
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(86400);
+        let msg = ExecuteMsg::ExecuteConfigChange { id: 1 };
+        execute(deps.as_mut(), env, info, msg).unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(20, "ujuno")]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[3].value, "3");
+
+        let history: PurchaseHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::PurchaseHistory {
+                    address: "buyer".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.purchases.len(), 2);
+        assert_eq!(history.purchases[0].id, 1);
+        assert_eq!(history.purchases[0].amount_paid, Uint128::from(10u128));
+        assert_eq!(history.purchases[0].amount_bought, Uint128::from(100u128));
+        assert_eq!(
+            history.purchases[0].price,
+            Decimal::from_str("0.1").unwrap()
+        );
+        assert_eq!(history.purchases[1].id, 3);
+        assert_eq!(history.purchases[1].amount_bought, Uint128::from(100u128));
+        assert_eq!(
+            history.purchases[1].price,
+            Decimal::from_str("0.2").unwrap()
+        );
+        let history: PurchaseHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::PurchaseHistory {
+                    address: "buyer".to_string(),
+                    start_after: Some(1),
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.purchases.len(), 1);
+        assert_eq!(history.purchases[0].id, 3);
+
+        // 30 paid for 200 tokens
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.price, Decimal::from_str("0.1").unwrap());
+        assert_eq!(position.average_price, Decimal::from_str("0.15").unwrap());
+    }
+
+    #[test]
+    pub fn test_claim_history() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        let buyer = mock_info("buyer", &[]);
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000100 + 1);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            buyer.clone(),
+            ExecuteMsg::Claim {
+                recipient: None,
+                token_id: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[4].key, "claim_id");
+        assert_eq!(res.attributes[4].value, "1");
+        // released batches are not paid twice
+        let err = execute(
+            deps.as_mut(),
+            env,
+            buyer.clone(),
+            ExecuteMsg::Claim {
+                recipient: None,
+                token_id: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::NoMatureClaims {}, err);
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000400 + 1);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            buyer,
+            ExecuteMsg::Claim {
+                recipient: None,
+                token_id: None,
+            },
+        )
+        .unwrap();
+
+        let history: ClaimHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ClaimHistory {
+                    address: "buyer".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.claims.len(), 2);
+        assert_eq!(history.claims[0].amount, Uint128::from(20u128));
+        assert_eq!(
+            history.claims[0].release_times,
+            vec![
+                Timestamp::from_nanos(100000000000),
+                Timestamp::from_nanos(100000000100)
+            ]
+        );
+        assert_eq!(history.claims[1].id, 2);
+        assert_eq!(history.claims[1].amount, Uint128::from(30u128));
+        assert_eq!(history.claims[1].timestamp, env.block.time);
+
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.batches.len(), 10);
+        assert_eq!(
+            position
+                .batches
+                .iter()
+                .filter(|batch| batch.released)
+                .count(),
+            5
+        );
+        assert_eq!(position.total_claimed, Uint128::from(50u128));
+    }
+
+    #[test]
+    pub fn test_claim_recipient_and_claim_for() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        // claim to a custodian
+        let buyer = mock_info("buyer", &[]);
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let msg = ExecuteMsg::Claim {
+            recipient: Some("custodian".to_string()),
+            token_id: None,
+        };
+        let res = execute(deps.as_mut(), env, buyer.clone(), msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "custodian".to_string(),
+                    amount: Uint128::from(10u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+
+        // keeper can not claim until the owner opts in
+        let keeper = mock_info("keeper", &[]);
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000100 + 1);
+        let msg = ExecuteMsg::ClaimFor {
+            owner: "buyer".to_string(),
+        };
+        let err = execute(deps.as_mut(), env.clone(), keeper.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::ClaimForNotAllowed {}, err);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::SetAllowClaimFor { allowed: true },
+        )
+        .unwrap();
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "buyer".to_string(),
+                    amount: Uint128::from(10u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+        let err = execute(deps.as_mut(), env.clone(), keeper.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::NoMatureClaims {}, err);
+
+        // opting out stops keepers again
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::SetAllowClaimFor { allowed: false },
+        )
+        .unwrap();
+        env.block.time = Timestamp::from_nanos(100000000200 + 1);
+        let err = execute(deps.as_mut(), env, keeper, msg).unwrap_err();
+        assert_eq!(ContractError::ClaimForNotAllowed {}, err);
+
+        let history: ClaimHistoryResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ClaimHistory {
+                    address: "buyer".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.claims[0].recipient, Addr::unchecked("custodian"));
+        assert_eq!(history.claims[1].recipient, Addr::unchecked("buyer"));
+    }
+
+    #[test]
+    pub fn test_distribute_matured() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        for buyer in ["buyer1", "buyer2", "buyer3"] {
+            let buyer = mock_info(buyer, &[Coin::new(10, "ujuno")]);
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                buyer,
+                ExecuteMsg::Buy { referrer: None },
+            )
+            .unwrap();
+        }
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000100 + 1);
+        // buyer1 claims before the push
+        let buyer1 = mock_info("buyer1", &[]);
+        let msg = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        execute(deps.as_mut(), env.clone(), buyer1.clone(), msg.clone()).unwrap();
+
+        let distribute = ExecuteMsg::DistributeMatured {
+            start_after: None,
+            limit: Some(2),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            buyer1.clone(),
+            distribute.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), distribute).unwrap();
+        let data: DistributeMaturedResponse = from_binary(&res.data.unwrap()).unwrap();
+        // buyer1 has nothing left to release
+        assert_eq!(data.recipients, 1);
+        assert_eq!(data.distributed, Uint128::from(20u128));
+        assert_eq!(data.next_start_after, Some("buyer2".to_string()));
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "buyer2".to_string(),
+                    amount: Uint128::from(20u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+
+        let distribute = ExecuteMsg::DistributeMatured {
+            start_after: data.next_start_after,
+            limit: Some(2),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, distribute).unwrap();
+        let data: DistributeMaturedResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.recipients, 1);
+        assert_eq!(data.next_start_after, None);
+
+        // pushed batches can not be claimed again
+        let buyer2 = mock_info("buyer2", &[]);
+        let err = execute(deps.as_mut(), env, buyer2, msg).unwrap_err();
+        assert_eq!(ContractError::NoMatureClaims {}, err);
+        let state: SaleStatsResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::SaleStats {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_claimed, Uint128::from(60u128));
+    }
+
+    // Minimal cw20, staking and cw721 contracts for multi-test
+    mod mock_contracts {
+        use cosmwasm_schema::cw_serde;
+        use cosmwasm_std::{
+            from_binary, to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response,
+            StdError, StdResult, Uint128,
+        };
+        use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
+        use cw_storage_plus::{Item, Map};
+
+        use crate::cw721::Metadata;
+
+        const BALANCES: Map<&Addr, Uint128> = Map::new("balances");
+        const STAKES: Map<&Addr, Uint128> = Map::new("stakes");
+        const MINTER: Item<Addr> = Item::new("minter");
+        const NFT_OWNERS: Map<&str, Addr> = Map::new("nft_owners");
+
+        #[cw_serde]
+        pub struct Cw20InstantiateMsg {
+            pub holder: String,
+            pub amount: Uint128,
+        }
+
+        pub fn cw20_instantiate(
+            deps: DepsMut,
+            _env: Env,
+            _info: MessageInfo,
+            msg: Cw20InstantiateMsg,
+        ) -> StdResult<Response> {
+            BALANCES.save(deps.storage, &Addr::unchecked(msg.holder), &msg.amount)?;
+            Ok(Response::default())
+        }
+
+        fn move_balance(deps: DepsMut, from: &Addr, to: &Addr, amount: Uint128) -> StdResult<()> {
+            BALANCES.update(deps.storage, from, |balance| -> StdResult<_> {
+                Ok(balance.unwrap_or_default().checked_sub(amount)?)
+            })?;
+            BALANCES.update(deps.storage, to, |balance| -> StdResult<_> {
+                Ok(balance.unwrap_or_default() + amount)
+            })?;
+            Ok(())
+        }
+
+        pub fn cw20_execute(
+            deps: DepsMut,
+            _env: Env,
+            info: MessageInfo,
+            msg: Cw20ExecuteMsg,
+        ) -> StdResult<Response> {
+            match msg {
+                Cw20ExecuteMsg::Transfer { recipient, amount } => {
+                    move_balance(deps, &info.sender, &Addr::unchecked(recipient), amount)?;
+                    Ok(Response::default())
+                }
+                Cw20ExecuteMsg::Send {
+                    contract,
+                    amount,
+                    msg,
+                } => {
+                    move_balance(deps, &info.sender, &Addr::unchecked(&contract), amount)?;
+                    let receive = Cw20ReceiveMsg {
+                        sender: info.sender.to_string(),
+                        amount,
+                        msg,
+                    };
+                    Ok(Response::default().add_message(receive.into_cosmos_msg(contract)?))
+                }
+                _ => Err(StdError::generic_err("unsupported")),
+            }
+        }
+
+        pub fn cw20_query(deps: Deps, _env: Env, msg: Cw20QueryMsg) -> StdResult<Binary> {
+            match msg {
+                Cw20QueryMsg::Balance { address } => to_binary(&BalanceResponse {
+                    balance: BALANCES
+                        .may_load(deps.storage, &Addr::unchecked(address))?
+                        .unwrap_or_default(),
+                }),
+                _ => Err(StdError::generic_err("unsupported")),
+            }
+        }
+
+        #[cw_serde]
+        pub enum StakingExecuteMsg {
+            Receive(Cw20ReceiveMsg),
+        }
+
+        #[cw_serde]
+        pub enum StakingHookMsg {
+            StakeFor { owner: String },
+        }
+
+        #[cw_serde]
+        pub enum StakingQueryMsg {
+            Staked { owner: String },
+        }
+
+        pub fn staking_instantiate(
+            _deps: DepsMut,
+            _env: Env,
+            _info: MessageInfo,
+            _msg: Empty,
+        ) -> StdResult<Response> {
+            Ok(Response::default())
+        }
+
+        pub fn staking_execute(
+            deps: DepsMut,
+            _env: Env,
+            _info: MessageInfo,
+            msg: StakingExecuteMsg,
+        ) -> StdResult<Response> {
+            let StakingExecuteMsg::Receive(receive) = msg;
+            let StakingHookMsg::StakeFor { owner } = from_binary(&receive.msg)?;
+            STAKES.update(
+                deps.storage,
+                &Addr::unchecked(owner),
+                |stake| -> StdResult<_> { Ok(stake.unwrap_or_default() + receive.amount) },
+            )?;
+            Ok(Response::default())
+        }
+
+        pub fn staking_query(deps: Deps, _env: Env, msg: StakingQueryMsg) -> StdResult<Binary> {
+            let StakingQueryMsg::Staked { owner } = msg;
+            to_binary(
+                &STAKES
+                    .may_load(deps.storage, &Addr::unchecked(owner))?
+                    .unwrap_or_default(),
+            )
+        }
+
+        #[cw_serde]
+        pub struct Cw721InstantiateMsg {
+            pub minter: String,
+        }
+
+        #[cw_serde]
+        pub enum Cw721ExecuteMsg {
+            Mint {
+                token_id: String,
+                owner: String,
+                token_uri: Option<String>,
+                extension: Metadata,
+            },
+            TransferNft {
+                recipient: String,
+                token_id: String,
+            },
+        }
+
+        #[cw_serde]
+        pub enum Cw721QueryMsg {
+            OwnerOf {
+                token_id: String,
+                include_expired: Option<bool>,
+            },
+        }
+
+        #[cw_serde]
+        pub struct OwnerOfResponse {
+            pub owner: String,
+            pub approvals: Vec<Empty>,
+        }
+
+        pub fn cw721_instantiate(
+            deps: DepsMut,
+            _env: Env,
+            _info: MessageInfo,
+            msg: Cw721InstantiateMsg,
+        ) -> StdResult<Response> {
+            MINTER.save(deps.storage, &Addr::unchecked(msg.minter))?;
+            Ok(Response::default())
+        }
+
+        pub fn cw721_execute(
+            deps: DepsMut,
+            _env: Env,
+            info: MessageInfo,
+            msg: Cw721ExecuteMsg,
+        ) -> StdResult<Response> {
+            match msg {
+                Cw721ExecuteMsg::Mint {
+                    token_id, owner, ..
+                } => {
+                    if info.sender != MINTER.load(deps.storage)? {
+                        return Err(StdError::generic_err("unauthorized"));
+                    }
+                    NFT_OWNERS.save(deps.storage, &token_id, &Addr::unchecked(owner))?;
+                }
+                Cw721ExecuteMsg::TransferNft {
+                    recipient,
+                    token_id,
+                } => {
+                    if info.sender != NFT_OWNERS.load(deps.storage, &token_id)? {
+                        return Err(StdError::generic_err("unauthorized"));
+                    }
+                    NFT_OWNERS.save(deps.storage, &token_id, &Addr::unchecked(recipient))?;
+                }
+            }
+            Ok(Response::default())
+        }
+
+        pub fn cw721_query(deps: Deps, _env: Env, msg: Cw721QueryMsg) -> StdResult<Binary> {
+            let Cw721QueryMsg::OwnerOf { token_id, .. } = msg;
+            to_binary(&OwnerOfResponse {
+                owner: NFT_OWNERS.load(deps.storage, &token_id)?.to_string(),
+                approvals: vec![],
+            })
+        }
+    }
+
+    #[test]
+    pub fn test_claim_and_stake() {
+        use cosmwasm_std::Empty;
+        use cw20::{BalanceResponse, Cw20QueryMsg};
+        use cw_multi_test::{App, ContractWrapper, Executor};
+        use mock_contracts::*;
+
+        let creator = Addr::unchecked("creator");
+        let buyer = Addr::unchecked("buyer");
+        let mut app = App::new(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &buyer, vec![Coin::new(10, "ujuno")])
+                .unwrap();
+        });
+        let cw20_code = app.store_code(Box::new(ContractWrapper::new(
+            cw20_execute,
+            cw20_instantiate,
+            cw20_query,
+        )));
+        let staking_code = app.store_code(Box::new(ContractWrapper::new(
+            staking_execute,
+            staking_instantiate,
+            staking_query,
+        )));
+        let launchpad_code =
+            app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+
+        let token = app
+            .instantiate_contract(
+                cw20_code,
+                creator.clone(),
+                &Cw20InstantiateMsg {
+                    holder: creator.to_string(),
+                    amount: Uint128::from(1000u128),
+                },
+                &[],
+                "token",
+                None,
+            )
+            .unwrap();
+        let staking = app
+            .instantiate_contract(
+                staking_code,
+                creator.clone(),
+                &Empty {},
+                &[],
+                "staking",
+                None,
+            )
+            .unwrap();
+        let other_staking = app
+            .instantiate_contract(staking_code, creator.clone(), &Empty {}, &[], "other", None)
+            .unwrap();
+        let mut init_msg = default_init_msg();
+        init_msg.sell_denom = token.to_string();
+        let launchpad = app
+            .instantiate_contract(
+                launchpad_code,
+                creator.clone(),
+                &init_msg,
+                &[],
+                "launchpad",
+                None,
+            )
+            .unwrap();
+        app.execute_contract(
+            creator.clone(),
+            token.clone(),
+            &Cw20ExecuteMsg::Transfer {
+                recipient: launchpad.to_string(),
+                amount: Uint128::from(1000u128),
+            },
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            creator.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::StartSale {},
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            buyer.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::Buy { referrer: None },
+            &[Coin::new(10, "ujuno")],
+        )
+        .unwrap();
+        app.execute_contract(
+            creator.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::StartDistribution {},
+            &[],
+        )
+        .unwrap();
+
+        // staking contract has to be allowed by admin
+        let msg = ExecuteMsg::ClaimAndStake {
+            staking_contract: staking.to_string(),
+        };
+        let err = app
+            .execute_contract(buyer.clone(), launchpad.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::StakingContractNotAllowed {},
+            err.downcast().unwrap()
+        );
+        let allow_msg = ExecuteMsg::UpdateStakingContract {
+            address: staking.to_string(),
+            hook_msg: Some(r#"{"stake_for":{"owner":"{owner}"}}"#.to_string()),
+        };
+        let err = app
+            .execute_contract(buyer.clone(), launchpad.clone(), &allow_msg, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+        app.execute_contract(creator, launchpad.clone(), &allow_msg, &[])
+            .unwrap();
+        let other_msg = ExecuteMsg::ClaimAndStake {
+            staking_contract: other_staking.to_string(),
+        };
+        let err = app
+            .execute_contract(buyer.clone(), launchpad.clone(), &other_msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::StakingContractNotAllowed {},
+            err.downcast().unwrap()
+        );
+
+        // every batch has matured at the app block time
+        app.execute_contract(buyer.clone(), launchpad.clone(), &msg, &[])
+            .unwrap();
+        let staked: Uint128 = app
+            .wrap()
+            .query_wasm_smart(
+                &staking,
+                &StakingQueryMsg::Staked {
+                    owner: buyer.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(staked, Uint128::from(100u128));
+        let balance: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &token,
+                &Cw20QueryMsg::Balance {
+                    address: staking.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(balance.balance, Uint128::from(100u128));
+
+        let history: ClaimHistoryResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &launchpad,
+                &QueryMsg::ClaimHistory {
+                    address: buyer.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(history.claims[0].recipient, staking);
+        let err = app
+            .execute_contract(buyer, launchpad, &msg, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NoMatureClaims {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    pub fn test_transfer_position() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        for (buyer, amount) in [("buyer1", 10), ("buyer2", 20)] {
+            let buyer = mock_info(buyer, &[Coin::new(amount, "ujuno")]);
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                buyer,
+                ExecuteMsg::Buy { referrer: None },
+            )
+            .unwrap();
+        }
+        let buyer1 = mock_info("buyer1", &[]);
+        let msg = ExecuteMsg::TransferPosition {
+            recipient: "buyer2".to_string(),
+            amount: Some(Uint128::from(45u128)),
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer1.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::SaleNotDistribution {}, err);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        execute(deps.as_mut(), env.clone(), buyer1.clone(), claim).unwrap();
+
+        // admin can disable transfers for the round
+        let mut update_msg = ExecuteMsg::UpdateConfig {
+            batch_duration: None,
+            batch_amount: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
+            price: None,
+            buy_denom: None,
+            sell_denom: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: Some(false),
+            position_nft: None,
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), update_msg.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), buyer1.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::TransfersDisabled {}, err);
+        if let ExecuteMsg::UpdateConfig {
+            transfers_enabled, ..
+        } = &mut update_msg
+        {
+            *transfers_enabled = Some(true);
+        }
+        execute(deps.as_mut(), mock_env(), info, update_msg).unwrap();
+
+        let self_msg = ExecuteMsg::TransferPosition {
+            recipient: "buyer1".to_string(),
+            amount: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), buyer1.clone(), self_msg).unwrap_err();
+        assert_eq!(ContractError::CannotTransferToSelf {}, err);
+        let too_much = ExecuteMsg::TransferPosition {
+            recipient: "buyer2".to_string(),
+            amount: Some(Uint128::from(91u128)),
+        };
+        let err = execute(deps.as_mut(), env.clone(), buyer1.clone(), too_much).unwrap_err();
+        assert_eq!(ContractError::InvalidTransferAmount {}, err);
+
+        // half of the 90 unclaimed tokens merge into buyer2's schedule
+        execute(deps.as_mut(), env.clone(), buyer1.clone(), msg).unwrap();
+        let query_position = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, address: &str| {
+            from_binary::<QueryPositionResponse>(
+                &query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::QueryPosition {
+                        address: address.to_string(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let position = query_position(&deps, "buyer1");
+        assert_eq!(position.total_bought, Uint128::from(55u128));
+        assert_eq!(position.total_claimed, Uint128::from(10u128));
+        assert_eq!(position.total_paid, Uint128::from(6u128));
+        assert!(position.batches[0].released);
+        assert_eq!(position.batches[1].amount, Uint128::from(5u128));
+        let position = query_position(&deps, "buyer2");
+        assert_eq!(position.batches.len(), 10);
+        assert_eq!(position.total_bought, Uint128::from(245u128));
+        assert_eq!(position.total_paid, Uint128::from(24u128));
+        assert_eq!(position.batches[0].amount, Uint128::from(20u128));
+        assert_eq!(position.batches[1].amount, Uint128::from(25u128));
+
+        // the rest goes to a new owner
+        let msg = ExecuteMsg::TransferPosition {
+            recipient: "new_owner".to_string(),
+            amount: None,
+        };
+        execute(deps.as_mut(), env.clone(), buyer1, msg).unwrap();
+        let position = query_position(&deps, "buyer1");
+        assert_eq!(position.total_bought, position.total_claimed);
+        let position = query_position(&deps, "new_owner");
+        assert_eq!(position.total_bought, Uint128::from(45u128));
+        assert_eq!(position.batches.len(), 9);
+        assert_eq!(position.batches[0].amount, Uint128::from(5u128));
+
+        // rounding dust comes from the latest batch
+        let buyer2 = mock_info("buyer2", &[]);
+        let msg = ExecuteMsg::TransferPosition {
+            recipient: "new_owner".to_string(),
+            amount: Some(Uint128::from(7u128)),
+        };
+        execute(deps.as_mut(), env, buyer2, msg).unwrap();
+        let position = query_position(&deps, "new_owner");
+        assert_eq!(position.total_bought, Uint128::from(52u128));
+        assert_eq!(position.batches[8].amount, Uint128::from(12u128));
+        let position = query_position(&deps, "buyer2");
+        assert_eq!(position.batches[9].amount, Uint128::from(18u128));
+        let unclaimed: Uint128 = position.batches.iter().map(|batch| batch.amount).sum();
+        assert_eq!(unclaimed, Uint128::from(238u128));
+    }
+
+    #[test]
+    pub fn test_position_nft() {
+        use crate::cw721::Metadata;
+        use cw20::{BalanceResponse, Cw20QueryMsg};
+        use cw_multi_test::{App, ContractWrapper, Executor};
+        use mock_contracts::*;
+
+        let creator = Addr::unchecked("creator");
+        let buyer = Addr::unchecked("buyer");
+        let trader = Addr::unchecked("trader");
+        let mut app = App::new(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &buyer, vec![Coin::new(10, "ujuno")])
+                .unwrap();
+            router
+                .bank
+                .init_balance(storage, &trader, vec![Coin::new(10, "ujuno")])
+                .unwrap();
+        });
+        let cw20_code = app.store_code(Box::new(ContractWrapper::new(
+            cw20_execute,
+            cw20_instantiate,
+            cw20_query,
+        )));
+        let cw721_code = app.store_code(Box::new(ContractWrapper::new(
+            cw721_execute,
+            cw721_instantiate,
+            cw721_query,
+        )));
+        let launchpad_code =
+            app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+
+        let token = app
+            .instantiate_contract(
+                cw20_code,
+                creator.clone(),
+                &Cw20InstantiateMsg {
+                    holder: creator.to_string(),
+                    amount: Uint128::from(1000u128),
+                },
+                &[],
+                "token",
+                None,
+            )
+            .unwrap();
+        let mut init_msg = default_init_msg();
+        init_msg.sell_denom = token.to_string();
+        let launchpad = app
+            .instantiate_contract(
+                launchpad_code,
+                creator.clone(),
+                &init_msg,
+                &[],
+                "launchpad",
+                None,
+            )
+            .unwrap();
+        let nft = app
+            .instantiate_contract(
+                cw721_code,
+                creator.clone(),
+                &Cw721InstantiateMsg {
+                    minter: launchpad.to_string(),
+                },
+                &[],
+                "positions",
+                None,
+            )
+            .unwrap();
+        app.execute_contract(
+            creator.clone(),
+            token.clone(),
+            &Cw20ExecuteMsg::Transfer {
+                recipient: launchpad.to_string(),
+                amount: Uint128::from(1000u128),
+            },
+            &[],
+        )
+        .unwrap();
+        let update_msg = ExecuteMsg::UpdateConfig {
+            batch_duration: None,
+            batch_amount: None,
+            revenue_recipients: None,
+            accumulate_revenue: None,
+            price: None,
+            buy_denom: None,
+            sell_denom: None,
+            cooling_off_period: None,
+            guardian: None,
+            max_pause_duration: None,
+            allowlist_enabled: None,
+            config_timelock: None,
+            transfers_enabled: None,
+            position_nft: Some(nft.to_string()),
+        };
+        app.execute_contract(creator.clone(), launchpad.clone(), &update_msg, &[])
+            .unwrap();
+
+        app.execute_contract(
+            creator.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::StartSale {},
+            &[],
+        )
+        .unwrap();
+        for sender in [&buyer, &trader] {
+            app.execute_contract(
+                sender.clone(),
+                launchpad.clone(),
+                &ExecuteMsg::Buy { referrer: None },
+                &[Coin::new(10, "ujuno")],
+            )
+            .unwrap();
+        }
+        app.execute_contract(
+            creator.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::StartDistribution {},
+            &[],
+        )
+        .unwrap();
+        // non-transferable rounds can not be minted
+        let mut transfers_msg = update_msg.clone();
+        if let ExecuteMsg::UpdateConfig {
+            transfers_enabled,
+            position_nft,
+            ..
+        } = &mut transfers_msg
+        {
+            *transfers_enabled = Some(false);
+            *position_nft = None;
+        }
+        app.execute_contract(creator.clone(), launchpad.clone(), &transfers_msg, &[])
+            .unwrap();
+        let err = app
+            .execute_contract(
+                buyer.clone(),
+                launchpad.clone(),
+                &ExecuteMsg::MintPositionNft {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(ContractError::TransfersDisabled {}, err.downcast().unwrap());
+        if let ExecuteMsg::UpdateConfig {
+            transfers_enabled, ..
+        } = &mut transfers_msg
+        {
+            *transfers_enabled = Some(true);
+        }
+        app.execute_contract(creator, launchpad.clone(), &transfers_msg, &[])
+            .unwrap();
+        app.execute_contract(
+            buyer.clone(),
+            launchpad.clone(),
+            &ExecuteMsg::MintPositionNft {},
+            &[],
+        )
+        .unwrap();
+        let owner: OwnerOfResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &nft,
+                &Cw721QueryMsg::OwnerOf {
+                    token_id: buyer.to_string(),
+                    include_expired: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(owner.owner, buyer.to_string());
+        let metadata: Metadata = app
+            .wrap()
+            .query_wasm_smart(
+                &launchpad,
+                &QueryMsg::PositionNftMetadata {
+                    token_id: buyer.to_string(),
+                },
+            )
+            .unwrap();
+        let attributes = metadata.attributes.unwrap();
+        assert_eq!(attributes[1].trait_type, "locked_amount");
+        assert_eq!(attributes[1].value, "100");
+        assert_eq!(attributes[3].trait_type, "unlock_100000000000");
+        assert_eq!(attributes[3].value, "10");
+
+        // tokenized positions can not receive tokens
+        let transfer = ExecuteMsg::TransferPosition {
+            recipient: buyer.to_string(),
+            amount: None,
+        };
+        let err = app
+            .execute_contract(trader.clone(), launchpad.clone(), &transfer, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::PositionTokenized {}, err.downcast().unwrap());
+
+        // listed tokens have to be back in the position before it is minted
+        let listing = ExecuteMsg::CreateListing {
+            amount: Uint128::from(10u128),
+            price: Decimal::percent(10),
+        };
+        app.execute_contract(trader.clone(), launchpad.clone(), &listing, &[])
+            .unwrap();
+        let err = app
+            .execute_contract(
+                trader.clone(),
+                launchpad.clone(),
+                &ExecuteMsg::MintPositionNft {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(ContractError::OpenListings {}, err.downcast().unwrap());
+
+        // claim rights follow the NFT
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        let err = app
+            .execute_contract(buyer.clone(), launchpad.clone(), &claim, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::PositionTokenized {}, err.downcast().unwrap());
+        app.execute_contract(
+            buyer.clone(),
+            nft,
+            &Cw721ExecuteMsg::TransferNft {
+                recipient: trader.to_string(),
+                token_id: buyer.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: Some(buyer.to_string()),
+        };
+        let err = app
+            .execute_contract(buyer, launchpad.clone(), &claim, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+        app.execute_contract(trader.clone(), launchpad, &claim, &[])
+            .unwrap();
+        let balance: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &token,
+                &Cw20QueryMsg::Balance {
+                    address: trader.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(balance.balance, Uint128::from(100u128));
+    }
+
+    #[test]
+    pub fn test_otc_listings() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let seller = mock_info("seller", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            seller,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        let msg = ExecuteMsg::UpdateOtcFee {
+            rate: Decimal::one(),
+            collector: "fee_collector".to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::InvalidOtcFee {}, err);
+        let msg = ExecuteMsg::UpdateOtcFee {
+            rate: Decimal::from_str("0.1").unwrap(),
+            collector: "fee_collector".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let seller = mock_info("seller", &[]);
+        let msg = ExecuteMsg::CreateListing {
+            amount: Uint128::from(50u128),
+            price: Decimal::from_str("0.3").unwrap(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), seller.clone(), msg).unwrap();
+        assert_eq!(res.attributes[1].value, "1");
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "seller".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(50u128));
+        assert_eq!(position.batches[0].amount, Uint128::from(5u128));
+        let listings: ListingsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Listings {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(listings.listings.len(), 1);
+        assert_eq!(listings.listings[0].seller, Addr::unchecked("seller"));
+        assert_eq!(listings.listings[0].seller_paid, Uint128::from(5u128));
+
+        let fill = ExecuteMsg::FillListing { id: 1 };
+        let err = execute(deps.as_mut(), mock_env(), seller.clone(), fill.clone()).unwrap_err();
+        assert_eq!(ContractError::CannotFillOwnListing {}, err);
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        let err = execute(deps.as_mut(), mock_env(), buyer, fill.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::InsufficientFunds {
+                required: Uint128::from(15u128)
+            },
+            err
+        );
+        let buyer = mock_info("buyer", &[Coin::new(20, "ujuno")]);
+        let res = execute(deps.as_mut(), mock_env(), buyer, fill.clone()).unwrap();
+        // 15 paid, 10% fee rounds down to 1 and 5 is refunded
+        assert_eq!(
+            res.messages
+                .iter()
+                .map(|msg| msg.msg.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "seller".to_string(),
+                    amount: vec![Coin::new(14, "ujuno")],
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "fee_collector".to_string(),
+                    amount: vec![Coin::new(1, "ujuno")],
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "buyer".to_string(),
+                    amount: vec![Coin::new(5, "ujuno")],
+                }),
+            ]
+        );
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(50u128));
+        assert_eq!(position.total_paid, Uint128::from(15u128));
+        assert_eq!(position.batches.len(), 10);
+        let buyer = mock_info("buyer", &[Coin::new(20, "ujuno")]);
+        execute(deps.as_mut(), mock_env(), buyer, fill).unwrap_err();
+
+        // cancelled listings go back to the seller
+        let msg = ExecuteMsg::CreateListing {
+            amount: Uint128::from(20u128),
+            price: Decimal::from_str("0.3").unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), seller.clone(), msg).unwrap();
+        let buyer = mock_info("buyer", &[]);
+        let cancel = ExecuteMsg::CancelListing { id: 2 };
+        let err = execute(deps.as_mut(), mock_env(), buyer, cancel.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), mock_env(), seller, cancel).unwrap();
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "seller".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(50u128));
+        assert_eq!(position.total_paid, Uint128::from(5u128));
+        assert_eq!(position.batches.len(), 10);
+        let listings: ListingsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ListingsBySeller {
+                    seller: "seller".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(listings.listings.is_empty());
+    }
+
+    #[test]
+    pub fn test_liens() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        // positions can not be pledged while purchases can still be cancelled
+        let msg = ExecuteMsg::GrantLien {
+            lender: "lender".to_string(),
+            redirect_claims: false,
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer, msg).unwrap_err();
+        assert_eq!(ContractError::SaleNotDistribution {}, err);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let msg = ExecuteMsg::UpdateLenders {
+            add: vec!["lender".to_string()],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let buyer = mock_info("buyer", &[]);
+        let lender = mock_info("lender", &[]);
+        let msg = ExecuteMsg::GrantLien {
+            lender: "stranger".to_string(),
+            redirect_claims: true,
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::LenderNotApproved {}, err);
+        let msg = ExecuteMsg::GrantLien {
+            lender: "lender".to_string(),
+            redirect_claims: true,
+        };
+        execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap();
+        let msg = ExecuteMsg::TransferPosition {
+            recipient: "other".to_string(),
+            amount: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::LienActive {}, err);
+
+        let value: PositionValueResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::PositionValue {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(value.unclaimed, Uint128::from(100u128));
+        assert_eq!(value.claimable, Uint128::from(100u128));
+        assert_eq!(value.value, Uint128::from(10u128));
+        assert_eq!(value.lien.unwrap().lender, Addr::unchecked("lender"));
+
+        // claims are redirected to the lender
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), buyer.clone(), claim.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "lender".to_string(),
+                    amount: Uint128::from(10u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+
+        let release = ExecuteMsg::ReleaseLien {
+            owner: "buyer".to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            release.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), mock_env(), lender.clone(), release.clone()).unwrap();
+
+        // blocking lien, then the lender takes over on default
+        let msg = ExecuteMsg::GrantLien {
+            lender: "lender".to_string(),
+            redirect_claims: false,
+        };
+        execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap();
+        env.block.time = Timestamp::from_nanos(100000000100 + 1);
+        let err = execute(deps.as_mut(), env, buyer, claim).unwrap_err();
+        assert_eq!(ContractError::LienActive {}, err);
+        let seize = ExecuteMsg::SeizePosition {
+            owner: "buyer".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), lender.clone(), seize).unwrap();
+        assert_eq!(res.attributes[3].value, "90");
+        let err = execute(deps.as_mut(), mock_env(), lender, release).unwrap_err();
+        assert_eq!(ContractError::NoLien {}, err);
+
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "lender".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(90u128));
+        assert_eq!(position.total_paid, Uint128::from(9u128));
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "buyer".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, position.total_claimed);
+        assert_eq!(position.lien, None);
+    }
+
+    #[test]
+    pub fn test_split_and_merge_positions() {
+        // every position keeps its totals in line with its batches and no tokens are created
+        fn assert_invariants(deps: cosmwasm_std::Deps) -> ExportPositionsResponse {
+            let export: ExportPositionsResponse = from_binary(
+                &query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::ExportPositions {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            let mut total_bought = Uint128::zero();
+            for position in &export.positions {
+                let batched: Uint128 = position.batches.iter().map(|b| b.amount).sum();
+                let released: Uint128 = position
+                    .batches
+                    .iter()
+                    .filter(|b| b.released)
+                    .map(|b| b.amount)
+                    .sum();
+                assert_eq!(batched, position.total_bought);
+                assert_eq!(released, position.total_claimed);
+                total_bought += position.total_bought;
+            }
+            assert_eq!(total_bought, Uint128::from(100u128));
+            export
+        }
+
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let buyer = mock_info("buyer", &[]);
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        execute(deps.as_mut(), env, buyer.clone(), claim).unwrap();
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer.clone(),
+            ExecuteMsg::SplitPosition { shares: vec![] },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::InvalidTransferAmount {}, err);
+        let msg = ExecuteMsg::SplitPosition {
+            shares: vec![
+                ("sub1".to_string(), Uint128::from(30u128)),
+                ("buyer".to_string(), Uint128::from(10u128)),
+            ],
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::CannotTransferToSelf {}, err);
+        // only the 90 unclaimed tokens can be split
+        let msg = ExecuteMsg::SplitPosition {
+            shares: vec![
+                ("sub1".to_string(), Uint128::from(50u128)),
+                ("sub2".to_string(), Uint128::from(45u128)),
+            ],
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::InvalidTransferAmount {}, err);
+
+        let msg = ExecuteMsg::SplitPosition {
+            shares: vec![
+                ("sub1".to_string(), Uint128::from(30u128)),
+                ("sub2".to_string(), Uint128::from(45u128)),
+            ],
+        };
+        let res = execute(deps.as_mut(), mock_env(), buyer.clone(), msg).unwrap();
+        assert_eq!(res.attributes[3].value, "75");
+        let export = assert_invariants(deps.as_ref());
+        assert_eq!(export.positions.len(), 3);
+        let buyer_position = &export.positions[0];
+        assert_eq!(buyer_position.total_bought, Uint128::from(25u128));
+        assert_eq!(buyer_position.total_claimed, Uint128::from(10u128));
+        assert_eq!(buyer_position.total_paid, Uint128::from(3u128));
+        let sub1 = &export.positions[1];
+        assert_eq!(sub1.total_bought, Uint128::from(30u128));
+        assert_eq!(sub1.total_claimed, Uint128::zero());
+        assert_eq!(sub1.batches.len(), 9);
+
+        // merge everything back into sub2, claimed history included
+        for owner in ["sub1", "buyer"] {
+            let msg = ExecuteMsg::MergeInto {
+                recipient: "sub2".to_string(),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(owner, &[]), msg).unwrap();
+        }
+        let export = assert_invariants(deps.as_ref());
+        assert_eq!(export.positions.len(), 1);
+        let merged = &export.positions[0];
+        assert_eq!(merged.address, Addr::unchecked("sub2"));
+        assert_eq!(merged.total_bought, Uint128::from(100u128));
+        assert_eq!(merged.total_claimed, Uint128::from(10u128));
+        assert_eq!(merged.total_paid, Uint128::from(10u128));
+        assert_eq!(merged.batches.len(), 10);
+        for batch in &merged.batches {
+            assert_eq!(batch.amount, Uint128::from(10u128));
+        }
+        let msg = ExecuteMsg::MergeInto {
+            recipient: "sub1".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), buyer, msg).unwrap_err();
+
+        // purchased tokens can not be merged into a grant
+        mock_token_balance(&mut deps, 1000);
+        let grant = ExecuteMsg::GrantAllocation {
+            address: "team".to_string(),
+            amount: Uint128::from(100u128),
+            schedule: None,
+            revocable: true,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), grant).unwrap();
+        let msg = ExecuteMsg::MergeInto {
+            recipient: "team".to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sub2", &[]), msg).unwrap_err();
+        assert_eq!(ContractError::PositionGranted {}, err);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::RevokeAllocation {
+                address: "team".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[3].value, "0");
+    }
+
+    #[test]
+    pub fn test_grant_allocation() {
+        let mut deps = mock_dependencies();
+        mock_token_balance(&mut deps, 1000);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+
+        let grant = ExecuteMsg::GrantAllocation {
+            address: "team".to_string(),
+            amount: Uint128::from(105u128),
+            schedule: None,
+            revocable: false,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("team", &[]),
+            grant.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), mock_env(), info.clone(), grant.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), grant).unwrap_err();
+        assert_eq!(ContractError::PositionExists {}, err);
+
+        // sale schedule, rounding dust unlocks last
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "team".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(105u128));
+        assert_eq!(position.total_paid, Uint128::zero());
+        assert_eq!(position.batches.len(), 10);
+        assert_eq!(position.batches[0].amount, Uint128::from(10u128));
+        assert_eq!(position.batches[9].amount, Uint128::from(15u128));
+
+        // custom schedule must add up to the amount
+        let mut grant = ExecuteMsg::GrantAllocation {
+            address: "advisor".to_string(),
+            amount: Uint128::from(50u128),
+            schedule: Some(vec![
+                UnlockBucket {
+                    release_time: Timestamp::from_nanos(100000000500),
+                    amount: Uint128::from(20u128),
+                },
+                UnlockBucket {
+                    release_time: Timestamp::from_nanos(100000000100),
+                    amount: Uint128::from(20u128),
+                },
+            ]),
+            revocable: false,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), grant.clone()).unwrap_err();
+        assert_eq!(ContractError::InvalidGrant {}, err);
+        if let ExecuteMsg::GrantAllocation {
+            schedule: Some(schedule),
+            ..
+        } = &mut grant
+        {
+            schedule[1].amount = Uint128::from(30u128);
+        }
+        execute(deps.as_mut(), mock_env(), info.clone(), grant).unwrap();
+        // grants have to be covered by the contract balance
+        let grant = ExecuteMsg::GrantAllocation {
+            address: "investor".to_string(),
+            amount: Uint128::from(846u128),
+            schedule: None,
+            revocable: false,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), grant).unwrap_err();
+        assert_eq!(ContractError::InsufficientBalance {}, err);
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "advisor".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.batches.len(), 2);
+        assert_eq!(position.batches[0].amount, Uint128::from(30u128));
+
+        // grants stay out of the sale
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("team", &[Coin::new(10, "ujuno")]),
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::PositionGranted {}, err);
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_sold, Uint128::from(100u128));
+        assert_eq!(state.total_revenue, Uint128::from(10u128));
+        assert_eq!(state.total_granted, Uint128::from(155u128));
+        let schedule: UnlockScheduleResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::UnlockSchedule {
+                    start: None,
+                    end: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(schedule.total, Uint128::from(255u128));
+
+        let grants: GrantsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Grants {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(grants.grants.len(), 2);
+        assert_eq!(grants.grants[0].address, Addr::unchecked("advisor"));
+        assert_eq!(grants.grants[1].amount, Uint128::from(105u128));
+
+        // sold and granted tokens can not be withdrawn
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::AdminWithdraw {
+                amount: Uint128::from(746u128),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::InsufficientBalance {}, err);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AdminWithdraw {
+                amount: Uint128::from(745u128),
+            },
+        )
+        .unwrap();
+
+        // grant positions can not leave the grantee
+        for msg in [
+            ExecuteMsg::CreateListing {
+                amount: Uint128::from(10u128),
+                price: Decimal::percent(10),
+            },
+            ExecuteMsg::TransferPosition {
+                recipient: "other".to_string(),
+                amount: None,
+            },
+        ] {
+            let err = execute(deps.as_mut(), mock_env(), mock_info("team", &[]), msg).unwrap_err();
+            assert_eq!(ContractError::PositionGranted {}, err);
+        }
+    }
+
+    #[test]
+    pub fn test_revoke_allocation() {
+        let mut deps = mock_dependencies();
+        mock_token_balance(&mut deps, 1000);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        for (address, revocable) in [("team", true), ("advisor", false)] {
+            let grant = ExecuteMsg::GrantAllocation {
+                address: address.to_string(),
+                amount: Uint128::from(100u128),
+                schedule: None,
+                revocable,
+            };
+            execute(deps.as_mut(), mock_env(), info.clone(), grant).unwrap();
+        }
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(10, "ujuno")]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartDistribution {},
+        )
+        .unwrap();
+
+        // purchased positions and plain grants can not be revoked
+        for address in ["buyer", "advisor"] {
+            let msg = ExecuteMsg::RevokeAllocation {
+                address: address.to_string(),
+            };
+            let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+            assert_eq!(ContractError::NotRevocable {}, err);
+        }
+        let team = mock_info("team", &[]);
+        let msg = ExecuteMsg::TransferPosition {
+            recipient: "other".to_string(),
+            amount: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), team.clone(), msg).unwrap_err();
+        assert_eq!(ContractError::PositionGranted {}, err);
+
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(100000000000 + 1);
+        let claim = ExecuteMsg::Claim {
+            recipient: None,
+            token_id: None,
+        };
+        execute(deps.as_mut(), env.clone(), team.clone(), claim.clone()).unwrap();
+
+        // three batches have unlocked, one of them is claimed already
+        env.block.time = Timestamp::from_nanos(100000000200 + 1);
+        let revoke = ExecuteMsg::RevokeAllocation {
+            address: "team".to_string(),
+        };
+        let err = execute(deps.as_mut(), env.clone(), team.clone(), revoke.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), revoke.clone()).unwrap();
+        assert_eq!(
+            res.messages
+                .iter()
+                .map(|msg| msg.msg.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "token".to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "team".to_string(),
+                        amount: Uint128::from(20u128),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "token".to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "creator".to_string(),
+                        amount: Uint128::from(70u128),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
+        );
+        let err = execute(deps.as_mut(), env.clone(), info, revoke).unwrap_err();
+        assert_eq!(ContractError::GrantRevoked {}, err);
+        env.block.time = Timestamp::from_nanos(100000000900 + 1);
+        let err = execute(deps.as_mut(), env, team, claim).unwrap_err();
+        assert_eq!(ContractError::NoMatureClaims {}, err);
+
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "team".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(30u128));
+        assert_eq!(position.total_claimed, Uint128::from(30u128));
+        assert_eq!(position.batches.len(), 3);
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_granted, Uint128::from(130u128));
+        let grants: GrantsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Grants {
+                    start_after: Some("buyer".to_string()),
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(grants.grants.len(), 1);
+        assert_eq!(
+            grants.grants[0].revoked_at,
+            Some(Timestamp::from_nanos(100000000201))
+        );
+        assert_eq!(grants.grants[0].clawed_back, Uint128::from(70u128));
+        let schedule: UnlockScheduleResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::UnlockSchedule {
+                    start: None,
+                    end: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(schedule.total, Uint128::from(230u128));
+    }
+
+    #[test]
+    pub fn test_referrals() {
+        fn referral_stats(deps: cosmwasm_std::Deps, address: &str) -> ReferralStats {
+            from_binary(
+                &query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::ReferralStats {
+                        address: address.to_string(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        }
+
+        // token rewards vest on the sale batches
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), default_init_msg()).unwrap();
+        let mut msg = ExecuteMsg::UpdateReferralConfig {
+            rate: Decimal::one(),
+            reward: ReferralReward::Tokens,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(ContractError::InvalidReferralRate {}, err);
+        if let ExecuteMsg::UpdateReferralConfig { rate, .. } = &mut msg {
+            *rate = Decimal::percent(10);
+        }
+        execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(ContractError::SaleNotPending {}, err);
+
+        let buyer = mock_info("buyer", &[Coin::new(100, "ujuno")]);
+        let buy = |referrer: &str| ExecuteMsg::Buy {
+            referrer: Some(referrer.to_string()),
+        };
+        let err = execute(deps.as_mut(), mock_env(), buyer.clone(), buy("buyer")).unwrap_err();
+        assert_eq!(ContractError::SelfReferral {}, err);
+        let msg = QueryMsg::SimulateBuy {
+            buyer: "buyer".to_string(),
+            funds: vec![Coin::new(100, "ujuno")],
+            referrer: Some("buyer".to_string()),
+        };
+        let quote: SimulateBuyResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            quote.error,
+            Some(ContractError::SelfReferral {}.to_string())
+        );
+        execute(deps.as_mut(), mock_env(), buyer.clone(), buy("promoter")).unwrap();
+        // the first referrer stays recorded
+        execute(deps.as_mut(), mock_env(), buyer, buy("other")).unwrap();
+
+        let stats = referral_stats(deps.as_ref(), "promoter");
+        assert_eq!(stats.referrals, 1);
+        assert_eq!(stats.purchases, 2);
+        assert_eq!(stats.volume, Uint128::from(200u128));
+        assert_eq!(stats.rewards, Uint128::from(200u128));
+        assert_eq!(
+            referral_stats(deps.as_ref(), "other"),
+            ReferralStats::default()
+        );
+        let position: QueryPositionResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryPosition {
+                    address: "promoter".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(position.total_bought, Uint128::from(200u128));
+        assert_eq!(position.total_paid, Uint128::zero());
+        assert_eq!(position.batches[0].amount, Uint128::from(20u128));
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_sold, Uint128::from(2000u128));
+        assert_eq!(state.total_referral_tokens, Uint128::from(200u128));
+
+        // granted referrers earn no tokens, the purchase still goes through
+        mock_token_balance(&mut deps, 10000);
+        let grant = ExecuteMsg::GrantAllocation {
+            address: "advisor".to_string(),
+            amount: Uint128::from(100u128),
+            schedule: None,
+            revocable: false,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), grant).unwrap();
+        let buyer2 = mock_info("buyer2", &[Coin::new(100, "ujuno")]);
+        execute(deps.as_mut(), mock_env(), buyer2.clone(), buy("advisor")).unwrap();
+        execute(deps.as_mut(), mock_env(), buyer2, buy("advisor")).unwrap();
+        let stats = referral_stats(deps.as_ref(), "advisor");
+        assert_eq!(stats.purchases, 2);
+        assert_eq!(stats.rewards, Uint128::zero());
+
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.total_referral_tokens, Uint128::from(200u128));
+
+        // a referrer holding only rewards can be referred on its first purchase
+        let promoter = mock_info("promoter", &[Coin::new(100, "ujuno")]);
+        execute(deps.as_mut(), mock_env(), promoter, buy("other")).unwrap();
+        let stats = referral_stats(deps.as_ref(), "other");
+        assert_eq!(stats.referrals, 1);
+        assert_eq!(stats.rewards, Uint128::from(100u128));
+
+        // payment rewards come out of the revenue, with or without a cooling-off period
+        for cooling_off_period in [0, 3600] {
+            let mut deps = mock_dependencies();
+            let info = mock_info("creator", &[]);
+            let mut init_msg = default_init_msg();
+            init_msg.cooling_off_period = Some(cooling_off_period);
+            instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+            let msg = ExecuteMsg::UpdateReferralConfig {
+                rate: Decimal::percent(5),
+                reward: ReferralReward::Payment,
+            };
+            execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::StartSale {},
+            )
+            .unwrap();
+            let buyer = mock_info("buyer", &[Coin::new(100, "ujuno")]);
+            let res = execute(deps.as_mut(), mock_env(), buyer, buy("promoter")).unwrap();
+            if cooling_off_period == 0 {
+                let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+                assert_eq!(
+                    msgs,
+                    vec![bank_send("revenue_collector", 95), bank_send("promoter", 5)]
+                );
+                continue;
+            }
+            assert!(res.messages.is_empty());
+
+            // cancelled purchases earn nothing
+            let buyer2 = mock_info("buyer2", &[Coin::new(40, "ujuno")]);
+            execute(deps.as_mut(), mock_env(), buyer2.clone(), buy("promoter")).unwrap();
+            let msg = ExecuteMsg::CancelPurchase { purchase_id: 2 };
+            execute(deps.as_mut(), mock_env(), buyer2, msg).unwrap();
+            let stats = referral_stats(deps.as_ref(), "promoter");
+            assert_eq!(stats.referrals, 2);
+            assert_eq!(stats.purchases, 1);
+            assert_eq!(stats.rewards, Uint128::from(5u128));
+            // also when the reward rounds down to zero
+            let buyer3 = mock_info("buyer3", &[Coin::new(10, "ujuno")]);
+            let res = execute(deps.as_mut(), mock_env(), buyer3.clone(), buy("promoter")).unwrap();
+            let msg = ExecuteMsg::CancelPurchase {
+                purchase_id: res.attributes[3].value.parse().unwrap(),
+            };
+            execute(deps.as_mut(), mock_env(), buyer3, msg).unwrap();
+            let stats = referral_stats(deps.as_ref(), "promoter");
+            assert_eq!(stats.purchases, 1);
+            assert_eq!(stats.volume, Uint128::from(100u128));
+
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::StartDistribution {},
+            )
+            .unwrap();
+            let res = execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::ForwardRevenue { limit: None },
+            )
+            .unwrap();
+            let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+            assert_eq!(
+                msgs,
+                vec![bank_send("revenue_collector", 95), bank_send("promoter", 5)]
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_revenue_split() {
+        fn split_init_msg() -> InstantiateMsg {
+            let mut init_msg = default_init_msg();
+            init_msg.revenue_recipients = vec![
+                ("treasury".to_string(), Decimal::percent(50)),
+                ("liquidity".to_string(), Decimal::percent(30)),
+                ("marketing".to_string(), Decimal::percent(20)),
+            ];
+            init_msg.platform_fee = Some(Decimal::percent(5));
+            init_msg.platform_fee_collector = Some("platform".to_string());
+            init_msg
+        }
+
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let mut init_msg = split_init_msg();
+        init_msg.revenue_recipients[2].1 = Decimal::percent(10);
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap_err();
+        assert_eq!(ContractError::InvalidRevenueRecipients {}, err);
+        let mut init_msg = split_init_msg();
+        init_msg.platform_fee_collector = None;
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap_err();
+        assert_eq!(ContractError::InvalidPlatformFee {}, err);
+
+        // paid out with each buy
+        instantiate(deps.as_mut(), mock_env(), info.clone(), split_init_msg()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(1000, "ujuno")]);
+        let buy = ExecuteMsg::Buy { referrer: None };
+        let res = execute(deps.as_mut(), mock_env(), buyer, buy.clone()).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                bank_send("platform", 50),
+                bank_send("treasury", 475),
+                bank_send("liquidity", 285),
+                bank_send("marketing", 190),
+            ]
+        );
+        // rounding dust goes to the first recipient
+        let buyer = mock_info("buyer", &[Coin::new(33, "ujuno")]);
+        let res = execute(deps.as_mut(), mock_env(), buyer, buy.clone()).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                bank_send("platform", 1),
+                bank_send("treasury", 17),
+                bank_send("liquidity", 9),
+                bank_send("marketing", 6),
+            ]
+        );
+
+        // only the platform can change its fee and it can not raise it after the start
+        let msg = ExecuteMsg::UpdatePlatformFee {
+            rate: Decimal::zero(),
+            collector: "platform".to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let raise = ExecuteMsg::UpdatePlatformFee {
+            rate: Decimal::percent(99),
+            collector: "platform".to_string(),
+        };
+        let err =
+            execute(deps.as_mut(), mock_env(), mock_info("platform", &[]), raise).unwrap_err();
+        assert_eq!(ContractError::SaleNotPending {}, err);
+        execute(deps.as_mut(), mock_env(), mock_info("platform", &[]), msg).unwrap();
+        let config: QueryConfigResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(config.platform_fee, None);
+        assert_eq!(config.revenue_recipients.len(), 3);
+
+        // accumulated and withdrawn in one go
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let mut init_msg = split_init_msg();
+        init_msg.accumulate_revenue = Some(true);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+        for _ in 0..2 {
+            let buyer = mock_info("buyer", &[Coin::new(500, "ujuno")]);
+            let res = execute(deps.as_mut(), mock_env(), buyer, buy.clone()).unwrap();
+            assert!(res.messages.is_empty());
+        }
+        let state: QueryStateResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryState {}).unwrap())
+                .unwrap();
+        assert_eq!(state.accumulated_revenue, Uint128::from(1000u128));
+        let anyone = mock_info("anyone", &[]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            anyone.clone(),
+            ExecuteMsg::WithdrawRevenue {},
+        )
+        .unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                bank_send("platform", 50),
+                bank_send("treasury", 475),
+                bank_send("liquidity", 285),
+                bank_send("marketing", 190),
+            ]
+        );
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            anyone,
+            ExecuteMsg::WithdrawRevenue {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::NoRevenueToWithdraw {}, err);
+    }
+
+    #[test]
+    pub fn test_revenue_vesting() {
+        fn vesting_at(deps: cosmwasm_std::Deps, time: Timestamp) -> RevenueVestingResponse {
+            let mut env = mock_env();
+            env.block.time = time;
+            from_binary(&query(deps, env, QueryMsg::RevenueVesting {}).unwrap()).unwrap()
+        }
+
+        let start = mock_env().block.time;
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let mut init_msg = default_init_msg();
+        init_msg.revenue_vesting = Some(RevenueVesting::Linear {
+            start,
+            cliff: 2000,
+            duration: 1000,
+        });
+        let err =
+            instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::InvalidRevenueVesting {}, err);
+        init_msg.revenue_vesting = Some(RevenueVesting::Linear {
+            start,
+            cliff: 100,
+            duration: 1000,
+        });
+        instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::StartSale {},
+        )
+        .unwrap();
+        // revenue is held even though accumulate_revenue is not set
+        let buyer = mock_info("buyer", &[Coin::new(1000, "ujuno")]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            buyer,
+            ExecuteMsg::Buy { referrer: None },
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+
+        let mut env = mock_env();
+        env.block.time = start.plus_seconds(50);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            ExecuteMsg::WithdrawRevenue {},
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::NoRevenueToWithdraw {}, err);
+        env.block.time = start.plus_seconds(500);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            ExecuteMsg::WithdrawRevenue {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "revenue_collector".to_string(),
+                amount: vec![Coin::new(500, "ujuno")],
+            })
+        );
+        let vesting = vesting_at(deps.as_ref(), start.plus_seconds(750));
+        assert_eq!(vesting.total, Uint128::from(1000u128));
+        assert_eq!(vesting.unlocked, Uint128::from(750u128));
+        assert_eq!(vesting.withdrawn, Uint128::from(500u128));
+        assert_eq!(vesting.withdrawable, Uint128::from(250u128));
+        env.block.time = start.plus_seconds(2000);
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::WithdrawRevenue {}).unwrap();
+        assert_eq!(res.attributes[1].value, "500");
+
+        // milestones unlock fixed shares
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let mut init_msg = default_init_msg();
+        let mut milestones = vec![
+            (start.plus_seconds(200), Decimal::percent(75)),
+            (start.plus_seconds(100), Decimal::percent(20)),
+        ];
+        init_msg.revenue_vesting = Some(RevenueVesting::Milestones {
+            milestones: milestones.clone(),
+        });
+        let err =
+            instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::InvalidRevenueVesting {}, err);
+        milestones[1].1 = Decimal::percent(25);
+        init_msg.revenue_vesting = Some(RevenueVesting::Milestones { milestones });
+        instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::StartSale {}).unwrap();
+        let buyer = mock_info("buyer", &[Coin::new(1000, "ujuno")]);
+        let buy = ExecuteMsg::Buy { referrer: None };
+        execute(deps.as_mut(), mock_env(), buyer, buy).unwrap();
+        let vesting = vesting_at(deps.as_ref(), start.plus_seconds(150));
+        assert_eq!(vesting.withdrawable, Uint128::from(250u128));
+        assert_eq!(
+            vesting.schedule,
+            Some(RevenueVesting::Milestones {
+                milestones: vec![
+                    (start.plus_seconds(100), Decimal::percent(25)),
+                    (start.plus_seconds(200), Decimal::percent(75)),
+                ]
+            })
+        );
+        let vesting = vesting_at(deps.as_ref(), start.plus_seconds(200));
+        assert_eq!(vesting.withdrawable, Uint128::from(1000u128));
+    }
 }